@@ -3,6 +3,15 @@
 //! Supports JSON and [MessagePack](https://msgpack.org) encoding.
 
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Encoding used when writing a replay.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum GdrFormat {
+    Json,
+    #[default]
+    MsgPack,
+}
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct BotInfo {
@@ -169,6 +178,23 @@ impl Replay {
             .or_else(|_| serde_json::from_slice(data))
     }
 
+    pub fn to_json_bytes(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec(self)
+    }
+
+    pub fn to_msgpack_bytes(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    /// Encodes the replay with the given format and writes it to `path`.
+    pub fn to_file(&self, path: &Path, format: GdrFormat) -> std::io::Result<()> {
+        let data = match format {
+            GdrFormat::Json => self.to_json_bytes()?,
+            GdrFormat::MsgPack => self.to_msgpack_bytes().map_err(std::io::Error::other)?,
+        };
+        std::fs::write(path, data)
+    }
+
     #[inline]
     pub fn frame_for_time(&self, time: f32) -> u32 {
         (time * self.framerate) as u32
@@ -1,7 +1,9 @@
 use anyhow::{Context, Result};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::io::{BufWriter, Cursor};
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use std::path::Path;
 use std::time::{Duration, Instant};
 use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Signal};
 use symphonia::core::codecs::DecoderOptions;
@@ -13,6 +15,33 @@ use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 use symphonia::core::sample::Sample;
 
+/// File format of rendered audio.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum AudioFormat {
+    #[default]
+    Wav,
+    Flac,
+    Ogg,
+}
+
+impl AudioFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Flac => "flac",
+            Self::Ogg => "ogg",
+        }
+    }
+
+    /// Guesses the format from the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        [Self::Wav, Self::Flac, Self::Ogg]
+            .into_iter()
+            .find(|f| f.extension() == ext)
+    }
+}
+
 /// Represents an audio sample. Stores a left and right channel.
 #[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub struct Frame {
@@ -138,6 +167,29 @@ fn interpolate_frame(
     ((c3 * fraction + c2) * fraction + c1) * fraction + c0
 }
 
+/// Plays a segment once on the default output device. Playback happens on a separate
+/// thread, so this returns immediately. Errors are logged.
+pub fn play_once(segment: &AudioSegment, sample_rate: u32) {
+    let samples: Vec<f32> = segment
+        .frames
+        .iter()
+        .flat_map(|f| [f.left, f.right])
+        .collect();
+    std::thread::spawn(move || {
+        let play = || -> Result<()> {
+            let (_stream, handle) = rodio::OutputStream::try_default()?;
+            let sink = rodio::Sink::try_new(&handle)?;
+            // frames are always stereo
+            sink.append(rodio::buffer::SamplesBuffer::new(2, sample_rate, samples));
+            sink.sleep_until_end();
+            Ok(())
+        };
+        if let Err(e) = play() {
+            log::error!("failed to play audio: {e}");
+        }
+    });
+}
+
 #[inline(always)]
 fn time_to_frame(sample_rate: u32, time: f64) -> usize {
     (time * sample_rate as f64) as usize
@@ -193,14 +245,35 @@ impl AudioSegment {
     }
 
     pub fn from_media_source(media_source: Box<dyn MediaSource>) -> Result<Self> {
+        Self::from_media_source_with_extension(media_source, None)
+    }
+
+    /// Opens and decodes an audio file. The file extension is used as a hint for the
+    /// format registry, which helps with formats that have no reliable header (e.g. MP3).
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let f = std::fs::File::open(path)?;
+        Self::from_media_source_with_extension(
+            Box::new(f),
+            path.extension().and_then(|ext| ext.to_str()),
+        )
+    }
+
+    pub fn from_media_source_with_extension(
+        media_source: Box<dyn MediaSource>,
+        extension: Option<&str>,
+    ) -> Result<Self> {
         use std::io::ErrorKind::UnexpectedEof;
 
         // create a media source stream from the provided media source
         let mss = MediaSourceStream::new(media_source, Default::default());
 
         // create a hint to help the format registry to guess what format
-        // the media source is using, we'll let symphonia figure that out for us
-        let hint = Hint::new();
+        // the media source is using, if there's no extension we'll let symphonia
+        // figure that out for us
+        let mut hint = Hint::new();
+        if let Some(extension) = extension {
+            hint.with_extension(extension);
+        }
 
         // use default options for reading and encoding
         let format_opts: FormatOptions = Default::default();
@@ -289,8 +362,21 @@ impl AudioSegment {
     }
 
     pub fn export_wav<W: std::io::Write + std::io::Seek>(&self, writer: W) -> Result<()> {
+        self.export_wav_channels(writer, 2)
+    }
+
+    /// Writes the segment as a WAV file with 1 (mono, channels are averaged) or 2 channels.
+    pub fn export_wav_channels<W: std::io::Write + std::io::Seek>(
+        &self,
+        writer: W,
+        channels: u16,
+    ) -> Result<()> {
+        anyhow::ensure!(
+            channels == 1 || channels == 2,
+            "unsupported number of channels {channels}, expected 1 or 2"
+        );
         let spec = hound::WavSpec {
-            channels: 2,
+            channels,
             sample_rate: self.sample_rate,
             bits_per_sample: 32,
             sample_format: hound::SampleFormat::Float,
@@ -302,8 +388,12 @@ impl AudioSegment {
         let mut wav =
             hound::WavWriter::new(BufWriter::with_capacity(16 * 1024 * 1024, writer), spec)?;
         for frame in &self.frames {
-            wav.write_sample(frame.left)?;
-            wav.write_sample(frame.right)?;
+            if channels == 1 {
+                wav.write_sample((frame.left + frame.right) / 2.0)?;
+            } else {
+                wav.write_sample(frame.left)?;
+                wav.write_sample(frame.right)?;
+            }
         }
         wav.finalize()?; // flush writer
 
@@ -311,6 +401,88 @@ impl AudioSegment {
         Ok(())
     }
 
+    /// Writes the segment as a stereo Ogg Vorbis file. `quality` is the Vorbis VBR quality
+    /// (oggenc's `-q` divided by 10), clamped to the -0.1 to 1.0 range of libvorbis.
+    /// Requires the `ogg` feature.
+    #[cfg(feature = "ogg")]
+    pub fn export_ogg<W: std::io::Write>(&self, writer: W, quality: f32) -> Result<()> {
+        use std::io::Write;
+        use std::num::{NonZeroU32, NonZeroU8};
+        use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder};
+        /// Frames passed to the encoder at once.
+        const CHUNK_SIZE: usize = 65536;
+        log::info!("writing ogg file");
+        let start = Instant::now();
+
+        let sample_rate = NonZeroU32::new(self.sample_rate).context("sample rate is 0")?;
+        let mut builder = VorbisEncoderBuilder::new(
+            sample_rate,
+            NonZeroU8::new(2).unwrap(),
+            BufWriter::with_capacity(16 * 1024 * 1024, writer),
+        )?;
+        builder.bitrate_management_strategy(VorbisBitrateManagementStrategy::QualityVbr {
+            target_quality: quality.clamp(-0.1, 1.0),
+        });
+        let mut encoder = builder.build()?;
+        for chunk in self.frames.chunks(CHUNK_SIZE) {
+            let left: Vec<f32> = chunk.iter().map(|f| f.left).collect();
+            let right: Vec<f32> = chunk.iter().map(|f| f.right).collect();
+            encoder.encode_audio_block([left, right])?;
+        }
+        encoder.finish()?.flush()?;
+
+        log::info!("finished writing ogg file in {:?}", start.elapsed());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "ogg"))]
+    pub fn export_ogg<W: std::io::Write>(&self, _writer: W, _quality: f32) -> Result<()> {
+        anyhow::bail!("zcb3 was built without the `ogg` feature")
+    }
+
+    /// Writes the segment in `format` to `path`. `ogg_quality` is only used for
+    /// [`AudioFormat::Ogg`], see [`AudioSegment::export_ogg`].
+    pub fn export_to_path(&self, path: &Path, format: AudioFormat, ogg_quality: f32) -> Result<()> {
+        let f = std::fs::File::create(path)?;
+        match format {
+            AudioFormat::Wav => self.export_wav(f),
+            AudioFormat::Flac => self.export_flac(f),
+            AudioFormat::Ogg => self.export_ogg(f, ogg_quality),
+        }
+    }
+
+    /// Writes the segment as a stereo 24-bit FLAC file. Samples are clamped to [-1, 1].
+    pub fn export_flac<W: std::io::Write>(&self, mut writer: W) -> Result<()> {
+        use flacenc::component::BitRepr;
+        use flacenc::error::Verify;
+
+        const SCALE: f32 = 8_388_607.0; // 2^23 - 1
+        log::info!("writing flac file");
+        let start = Instant::now();
+
+        let quantize = |sample: f32| (sample.clamp(-1.0, 1.0) * SCALE).round() as i32;
+        let samples: Vec<i32> = self
+            .frames
+            .iter()
+            .flat_map(|f| [quantize(f.left), quantize(f.right)])
+            .collect();
+        let config = flacenc::config::Encoder::default()
+            .into_verified()
+            .map_err(|(_, e)| anyhow::anyhow!("invalid flac encoder config: {e}"))?;
+        let source =
+            flacenc::source::MemSource::from_samples(&samples, 2, 24, self.sample_rate as usize);
+        let stream = flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+            .map_err(|e| anyhow::anyhow!("failed to encode flac: {e}"))?;
+        let mut sink = flacenc::bitsink::ByteSink::new();
+        stream
+            .write(&mut sink)
+            .map_err(|e| anyhow::anyhow!("failed to write flac stream: {e}"))?;
+        writer.write_all(sink.as_slice())?;
+
+        log::info!("finished writing flac file in {:?}", start.elapsed());
+        Ok(())
+    }
+
     /// Convert time to samples. Clamps maximum to the segment length.
     #[inline(always)]
     fn time_to_frame(&self, time: f64) -> usize {
@@ -334,6 +506,18 @@ impl AudioSegment {
 
     #[inline]
     pub fn overlay_at_vol(&mut self, time: f64, other: &AudioSegment, volume: f32, dur: f64) {
+        self.overlay_at_stereo_vol(time, other, (volume, volume), dur);
+    }
+
+    /// Same as [`AudioSegment::overlay_at_vol`], but with separate volumes for the left and
+    /// right channel, for panning.
+    pub fn overlay_at_stereo_vol(
+        &mut self,
+        time: f64,
+        other: &AudioSegment,
+        (left_volume, right_volume): (f32, f32),
+        dur: f64,
+    ) {
         assert!(self.sample_rate == other.sample_rate);
 
         let start = self.time_to_frame(time);
@@ -343,17 +527,111 @@ impl AudioSegment {
             .par_iter_mut() // run in parallel
             .zip(&other.frames)
             .for_each(|(s, o)| {
-                s.left += o.left * volume;
-                s.right += o.right * volume;
+                s.left += o.left * left_volume;
+                s.right += o.right * right_volume;
             });
     }
 
+    /// Returns `self` followed by `other`, with the last `crossfade_samples` frames of `self`
+    /// linearly crossfaded into the first frames of `other`. The crossfade is shortened if
+    /// either segment is shorter than `crossfade_samples`.
+    pub fn mix_with_crossfade(&self, other: &AudioSegment, crossfade_samples: usize) -> Self {
+        assert!(self.sample_rate == other.sample_rate);
+
+        let crossfade = crossfade_samples
+            .min(self.frames.len())
+            .min(other.frames.len());
+        let split = self.frames.len() - crossfade;
+        let mut frames = Vec::with_capacity(split + other.frames.len());
+        frames.extend_from_slice(&self.frames[..split]);
+        frames.extend(
+            self.frames[split..]
+                .iter()
+                .zip(&other.frames[..crossfade])
+                .enumerate()
+                .map(|(i, (&a, &b))| {
+                    let t = (i + 1) as f32 / (crossfade + 1) as f32;
+                    a * (1.0 - t) + b * t
+                }),
+        );
+        frames.extend_from_slice(&other.frames[crossfade..]);
+        Self {
+            sample_rate: self.sample_rate,
+            frames,
+            pitch_table: vec![],
+        }
+    }
+
+    /// Returns the frames as interleaved (left, right) `f64` samples, for DSP that needs
+    /// more precision than `f32`.
+    #[cfg(feature = "high-precision-dsp")]
+    pub fn to_f64_samples(&self) -> Vec<f64> {
+        self.frames
+            .iter()
+            .flat_map(|f| [f.left as f64, f.right as f64])
+            .collect()
+    }
+
+    /// Builds a segment from interleaved (left, right) `f64` samples, as returned by
+    /// [`AudioSegment::to_f64_samples`]. A trailing unpaired sample is dropped.
+    #[cfg(feature = "high-precision-dsp")]
+    pub fn from_f64_samples(samples: Vec<f64>, sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            frames: samples
+                .chunks_exact(2)
+                .map(|s| Frame::new(s[0] as f32, s[1] as f32))
+                .collect(),
+            pitch_table: vec![],
+        }
+    }
+
+    /// Removes low-frequency rumble below `cutoff_hz`. Two first-order high-pass stages
+    /// are cascaded for a 12 dB/octave rolloff.
+    pub fn apply_high_pass_filter(&mut self, cutoff_hz: f32, sample_rate: u32) -> &mut Self {
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff_hz);
+        let dt = 1.0 / sample_rate as f32;
+        let a = rc / (rc + dt);
+        for _ in 0..2 {
+            // y[n] = a * y[n-1] + a * (x[n] - x[n-1])
+            let mut prev_x = self.frames.first().copied().unwrap_or(Frame::ZERO);
+            let mut prev_y = Frame::ZERO;
+            for frame in &mut self.frames {
+                let x = *frame;
+                *frame = (prev_y + x - prev_x) * a;
+                prev_x = x;
+                prev_y = *frame;
+            }
+        }
+        self
+    }
+
+    /// Returns the number of channels in the source audio: 1 if both channels of every
+    /// frame are identical, 2 otherwise. Frames are always stored as stereo (mono files
+    /// are duplicated into both channels on load), so mixing never has to up- or down-mix.
+    pub fn channel_count(&self) -> u32 {
+        if self.frames.iter().all(|f| f.left == f.right) {
+            1
+        } else {
+            2
+        }
+    }
+
     /// Returns the duration of the audio segment.
     #[inline]
     pub fn duration(&self) -> Duration {
         Duration::from_secs_f64(self.frames.len() as f64 / self.sample_rate as f64)
     }
 
+    /// Changes the pitch by `ratio` (e.g. 0.5 is an octave down) without changing the
+    /// sample rate. The length changes by `1 / ratio`.
+    pub fn pitch_shift(&mut self, ratio: f32) -> &mut Self {
+        let sample_rate = self.sample_rate;
+        self.resample((sample_rate as f32 * ratio) as u32);
+        self.sample_rate = sample_rate; // keep same sample rate
+        self
+    }
+
     /// Uses sinc interpolation to resample the audio to the given rate.
     ///
     /// Does not do anything if sample rate is the same.
@@ -438,11 +716,11 @@ impl AudioSegment {
     /// Chooses random pitch from the pitch table. If pitch table is not generated,
     /// returns [`self`]    
     #[inline]
-    pub fn random_pitch(&self) -> &AudioSegment {
+    pub fn random_pitch(&self, rng: &mut fastrand::Rng) -> &AudioSegment {
         if self.pitch_table.is_empty() {
             return self;
         }
-        &self.pitch_table[fastrand::usize(..self.pitch_table.len())]
+        &self.pitch_table[rng.usize(..self.pitch_table.len())]
     }
 
     pub fn get_sample_index_which_was_a_duration_ago(&self, ago: Duration) -> usize {
@@ -461,8 +739,8 @@ impl AudioSegment {
     pub fn remove_silence_from_start(&mut self, threshold: f32) {
         let mut idx = 0;
         for (i, v) in self.frames.iter().enumerate() {
-            let avg = (v.left + v.right) / 2.; // avg of l and r channels
-            if avg.abs() > threshold {
+            // check each channel, so out of phase stereo audio isn't treated as silence
+            if v.left.abs().max(v.right.abs()) > threshold {
                 idx = i;
                 break;
             }
@@ -475,8 +753,8 @@ impl AudioSegment {
     pub fn remove_silence_from_end(&mut self, threshold: f32) {
         let mut idx = 0;
         for (i, v) in self.frames.iter().rev().enumerate() {
-            let avg = (v.left + v.right) / 2.; // avg of l and r channels
-            if avg.abs() > threshold {
+            // check each channel, so out of phase stereo audio isn't treated as silence
+            if v.left.abs().max(v.right.abs()) > threshold {
                 idx = i;
                 break;
             }
@@ -493,6 +771,25 @@ impl AudioSegment {
         self
     }
 
+    /// Linearly fades in the first `duration` of the audio from silence.
+    pub fn apply_fade_in(&mut self, duration: Duration) -> &mut Self {
+        let len = time_to_frame(self.sample_rate, duration.as_secs_f64()).min(self.frames.len());
+        for (i, frame) in self.frames[..len].iter_mut().enumerate() {
+            *frame *= i as f32 / len as f32;
+        }
+        self
+    }
+
+    /// Linearly fades out the last `duration` of the audio to silence.
+    pub fn apply_fade_out(&mut self, duration: Duration) -> &mut Self {
+        let len = time_to_frame(self.sample_rate, duration.as_secs_f64()).min(self.frames.len());
+        let start = self.frames.len() - len;
+        for (i, frame) in self.frames[start..].iter_mut().enumerate() {
+            *frame *= (len - i - 1) as f32 / len as f32;
+        }
+        self
+    }
+
     pub fn reverse(&mut self) -> &mut Self {
         self.frames.reverse();
         self
@@ -531,4 +828,146 @@ mod tests {
         let sample = segment.time_to_frame(3.14);
         assert!(segment.frames.get(sample).is_some());
     }
+
+    #[test]
+    fn test_audio_format_from_path() {
+        let format = |path: &str| AudioFormat::from_path(Path::new(path));
+        assert_eq!(format("out.wav"), Some(AudioFormat::Wav));
+        assert_eq!(format("dir/out.FLAC"), Some(AudioFormat::Flac));
+        assert_eq!(format("out.ogg"), Some(AudioFormat::Ogg));
+        assert_eq!(format("out.mp3"), None);
+        assert_eq!(format("out"), None);
+    }
+
+    #[test]
+    fn test_pitch_shift() {
+        let mut down = AudioSegment::silent(1000, 1.0);
+        down.pitch_shift(0.5);
+        assert_eq!(down.sample_rate, 1000);
+        // the resampler drops a few frames at the end
+        assert!((down.frames.len() as f64 / 2000.0 - 1.0).abs() < 0.01);
+
+        let mut up = AudioSegment::silent(1000, 1.0);
+        up.pitch_shift(2.0);
+        assert_eq!(up.sample_rate, 1000);
+        assert!((up.frames.len() as f64 / 500.0 - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_export_flac() {
+        // a few blocks of a chirp followed by silence, with a partial last block
+        let mut segment = AudioSegment::silent(44100, 0.5);
+        for (i, frame) in segment.frames.iter_mut().take(10000).enumerate() {
+            let t = i as f32 / 44100.0;
+            frame.left = (t * t * 20000.0).sin() * 0.8;
+            frame.right = -frame.left / 2.0;
+        }
+        segment.frames[0].left = 2.0; // clamped
+
+        let mut flac = vec![];
+        segment.export_flac(&mut flac).unwrap();
+        let mut wav = Cursor::new(vec![]);
+        segment.export_wav(&mut wav).unwrap();
+        assert!(flac.len() < wav.get_ref().len() / 2);
+
+        let decoded = AudioSegment::from_media_source_with_extension(
+            Box::new(Cursor::new(flac)),
+            Some("flac"),
+        )
+        .unwrap();
+        assert_eq!(decoded.sample_rate, 44100);
+        assert_eq!(decoded.frames.len(), segment.frames.len());
+        for (a, b) in segment.frames.iter().zip(&decoded.frames) {
+            assert!((a.left.clamp(-1.0, 1.0) - b.left).abs() < 1e-6);
+            assert!((a.right - b.right).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_fades() {
+        let mut segment = AudioSegment::silent(100, 1.0);
+        segment.frames.fill(Frame::from_mono(1.0));
+        segment
+            .apply_fade_in(Duration::from_millis(100))
+            .apply_fade_out(Duration::from_millis(100));
+        assert_eq!(segment.frames[0].left, 0.0);
+        assert_eq!(segment.frames[5].left, 0.5);
+        assert_eq!(segment.frames[50].left, 1.0);
+        assert_eq!(segment.frames.last().unwrap().left, 0.0);
+
+        // fades longer than the audio are clamped
+        segment.apply_fade_in(Duration::from_secs(10));
+        assert_eq!(segment.frames[0].left, 0.0);
+    }
+
+    #[test]
+    fn test_mix_with_crossfade() {
+        let mut a = AudioSegment::silent(100, 1.0);
+        a.frames.fill(Frame::from_mono(1.0));
+        let b = AudioSegment::silent(100, 1.0);
+
+        let mixed = a.mix_with_crossfade(&b, 10);
+        assert_eq!(mixed.frames.len(), a.frames.len() + b.frames.len() - 10);
+        // the crossfade region ramps down from `a` to `b` without jumps
+        let ramp = &mixed.frames[a.frames.len() - 11..a.frames.len()];
+        assert!(ramp.windows(2).all(|w| w[1].left < w[0].left));
+        assert_eq!(mixed.frames[a.frames.len()].left, 0.0);
+
+        // crossfade longer than the segments is clamped
+        let mixed = a.mix_with_crossfade(&b, 10_000);
+        assert_eq!(mixed.frames.len(), b.frames.len());
+    }
+
+    #[test]
+    fn test_high_pass_filter() {
+        const SAMPLE_RATE: u32 = 44100;
+        let rms = |frames: &[Frame]| {
+            (frames.iter().map(|f| f.left * f.left).sum::<f32>() / frames.len() as f32).sqrt()
+        };
+
+        let mut segment = AudioSegment::silent(SAMPLE_RATE, 1.0);
+        for (i, frame) in segment.frames.iter_mut().enumerate() {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            *frame = Frame::from_mono((2.0 * std::f32::consts::PI * 50.0 * t).sin());
+        }
+        let before = rms(&segment.frames);
+        segment.apply_high_pass_filter(200.0, SAMPLE_RATE);
+
+        // skip the first half to let the filter settle
+        let half = segment.frames.len() / 2;
+        let after = rms(&segment.frames[half..]);
+        let attenuation_db = 20.0 * (after / before).log10();
+        assert!(
+            attenuation_db <= -20.0,
+            "50 Hz only attenuated by {attenuation_db} dB"
+        );
+    }
+
+    #[test]
+    fn test_overlay_stereo() {
+        let mut output = AudioSegment::silent(44100, 1.0);
+        let mut click = AudioSegment::silent(44100, 0.1);
+        click.frames.fill(Frame::new(0.5, -0.25));
+        assert_eq!(output.channel_count(), 1);
+        assert_eq!(click.channel_count(), 2);
+
+        output.overlay_at_vol(0.0, &click, 0.5, f64::INFINITY);
+        let frame = output.frames[100];
+        assert_eq!(frame.left, 0.25);
+        assert_eq!(frame.right, -0.125);
+        // the amplitude must not leak past the end of the click
+        assert_eq!(output.frames[click.frames.len() + 1], Frame::ZERO);
+    }
+
+    #[test]
+    fn test_overlay_panned() {
+        let mut output = AudioSegment::silent(44100, 1.0);
+        let mut click = AudioSegment::silent(44100, 0.1);
+        click.frames.fill(Frame::from_mono(0.5));
+
+        output.overlay_at_stereo_vol(0.0, &click, (1.0, 0.25), f64::INFINITY);
+        let frame = output.frames[100];
+        assert_eq!(frame.left, 0.5);
+        assert_eq!(frame.right, 0.125);
+    }
 }
@@ -1,11 +1,13 @@
-use crate::{f32_range, AudioSegment, Click, ClickType, ExtendedAction, Player, Replay};
+use crate::{rng_f32_range, AudioSegment, Click, ClickType, ExtendedAction, Frame, Player, Replay};
 use anyhow::Result;
 use fasteval2::Compiler;
 use serde::{Deserialize, Serialize};
 use std::{
     collections::BTreeMap,
+    io::Write,
     ops::{Deref, DerefMut, Index, IndexMut},
     path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
     time::{Duration, Instant},
 };
 
@@ -13,11 +15,28 @@ use std::{
 pub struct AudioFile {
     pub segment: AudioSegment,
     pub filename: String,
+    /// Cached peak amplitude, computed when the file is created.
+    peak: f32,
 }
 
 impl AudioFile {
-    pub const fn new(segment: AudioSegment, filename: String) -> Self {
-        Self { segment, filename }
+    pub fn new(segment: AudioSegment, filename: String) -> Self {
+        let peak = segment
+            .frames
+            .iter()
+            .map(|f| f.left.abs().max(f.right.abs()))
+            .fold(0.0_f32, f32::max);
+        Self {
+            segment,
+            filename,
+            peak,
+        }
+    }
+
+    /// Returns the highest absolute sample value of the file.
+    #[inline]
+    pub fn peak_amplitude(&self) -> f32 {
+        self.peak
     }
 }
 
@@ -50,6 +69,7 @@ pub struct PlayerClicks {
 impl Index<usize> for PlayerClicks {
     type Output = Vec<AudioFile>;
     fn index(&self, index: usize) -> &Self::Output {
+        // indices are the same as `ClickType::to_index`
         match index {
             0 => &self.hardclicks,
             1 => &self.hardreleases,
@@ -143,6 +163,12 @@ impl PlayerClicks {
         }
     }
 
+    /// Drops all loaded sounds and loads them again from `path`. Useful when the clickpack
+    /// files were edited on disk.
+    pub fn reload_from_path(&mut self, path: &Path, pitch: Pitch, sample_rate: u32) {
+        *self = Self::from_path(path, pitch, sample_rate);
+    }
+
     pub fn from_path(path: &Path, pitch: Pitch, sample_rate: u32) -> Self {
         let mut player = PlayerClicks::default();
         let mut path = fix_root_subdir(path);
@@ -203,39 +229,42 @@ impl PlayerClicks {
     }
 
     /// Choose a random click based on a click type.
-    pub fn random_click(&self, click_type: ClickType) -> Option<&AudioSegment> {
-        macro_rules! rand_click {
-            ($arr:expr) => {{
-                let len = $arr.len();
-                if len == 0 {
-                    continue;
-                }
-                $arr.get(fastrand::usize(..len))
-            }};
-        }
-
+    pub fn random_click(
+        &self,
+        click_type: ClickType,
+        rng: &mut fastrand::Rng,
+    ) -> Option<&AudioSegment> {
         let preferred = click_type.preferred();
         for typ in preferred {
-            use ClickType::*;
-
-            let click = match typ {
-                HardClick => rand_click!(self.hardclicks),
-                HardRelease => rand_click!(self.hardreleases),
-                Click => rand_click!(self.clicks),
-                Release => rand_click!(self.releases),
-                SoftClick => rand_click!(self.softclicks),
-                SoftRelease => rand_click!(self.softreleases),
-                MicroClick => rand_click!(self.microclicks),
-                MicroRelease => rand_click!(self.microreleases),
-                None => continue,
-            };
-            if let Some(click) = click {
-                return Some(click);
+            if typ == ClickType::None {
+                continue;
+            }
+            let files = &self[typ.to_index()];
+            if !files.is_empty() {
+                return Some(&files[rng.usize(..files.len())]);
             }
         }
         None
     }
 
+    /// Returns all files with a peak amplitude above `threshold`.
+    pub fn clipping_files(&self, threshold: f32) -> Vec<&AudioFile> {
+        [
+            &self.hardclicks,
+            &self.hardreleases,
+            &self.clicks,
+            &self.releases,
+            &self.softclicks,
+            &self.softreleases,
+            &self.microclicks,
+            &self.microreleases,
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|f| f.peak_amplitude() > threshold)
+        .collect()
+    }
+
     /// Finds the longest click amongst all clicks.
     pub fn longest_click(&self) -> f64 {
         let mut max = 0.0f64;
@@ -412,6 +441,21 @@ pub struct ClickpackConversionSettings {
     /// Whether to rename files to '1.wav', '2.wav', etc.
     #[serde(default = "bool::default")]
     pub rename_files: bool,
+    /// Pitch ratio to resample every file by (e.g. 0.9 = about one semitone down).
+    #[serde(default)]
+    pub pitch_shift: Option<f32>,
+    /// Cutoff frequency of the high-pass filter used to remove low-frequency rumble.
+    #[serde(default)]
+    pub high_pass_cutoff_hz: Option<f32>,
+    /// Whether to write mono files. Files keep their channels otherwise.
+    #[serde(default = "bool::default")]
+    pub force_mono: bool,
+    /// Length of the fade in at the start of every file, in milliseconds.
+    #[serde(default)]
+    pub fade_in_ms: u32,
+    /// Length of the fade out at the end of every file, in milliseconds.
+    #[serde(default)]
+    pub fade_out_ms: u32,
 }
 
 impl Default for ClickpackConversionSettings {
@@ -423,6 +467,31 @@ impl Default for ClickpackConversionSettings {
             remove_silence: RemoveSilenceFrom::None,
             silence_threshold: 0.05,
             rename_files: false,
+            pitch_shift: None,
+            high_pass_cutoff_hz: None,
+            force_mono: false,
+            fade_in_ms: 0,
+            fade_out_ms: 0,
+        }
+    }
+}
+
+/// How click types are chosen for actions.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ClassificationMode {
+    /// Use the time between actions.
+    #[default]
+    ByTime,
+    /// Use the Y velocity of the player at the time of the action. The timings are
+    /// used as velocity thresholds.
+    ByVelocity,
+}
+
+impl std::fmt::Display for ClassificationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ByTime => write!(f, "Time between actions"),
+            Self::ByVelocity => write!(f, "Y velocity"),
         }
     }
 }
@@ -436,6 +505,13 @@ pub struct VolumeSettings {
     pub change_releases_volume: bool,
     pub global_volume: f32,
     pub volume_var: f32,
+    /// Volume added to clicks that happen together with another click.
+    #[serde(default)]
+    pub simultaneous_click_boost: f32,
+    /// How far player 1 is panned to the left and player 2 to the right in two-player
+    /// replays, from 0 (centered) to 1 (one channel each).
+    #[serde(default)]
+    pub stereo_separation: f32,
 }
 
 impl Default for VolumeSettings {
@@ -448,6 +524,20 @@ impl Default for VolumeSettings {
             change_releases_volume: false,
             global_volume: 1.0,
             volume_var: 0.2,
+            simultaneous_click_boost: 0.0,
+            stereo_separation: 0.0,
+        }
+    }
+}
+
+impl VolumeSettings {
+    /// Left and right channel volumes for the clicks of `player`, see
+    /// [`VolumeSettings::stereo_separation`].
+    pub fn stereo_volumes(&self, player: Player) -> (f32, f32) {
+        let separation = self.stereo_separation.clamp(0.0, 1.0);
+        match player {
+            Player::One => (1.0, 1.0 - separation),
+            Player::Two => (1.0 - separation, 1.0),
         }
     }
 }
@@ -458,32 +548,41 @@ fn read_clicks_in_directory(dir: &Path, pitch: Pitch, sample_rate: u32) -> Vec<A
         dir.to_str().unwrap_or("")
     );
 
-    let mut segments = Vec::new();
     let Ok(dir) = dir.read_dir() else {
         log::warn!("can't find directory {dir:?}, skipping");
         return vec![];
     };
+    let paths: Vec<PathBuf> = dir
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .collect();
+
+    let decode = |path: &PathBuf| {
+        log::info!("decoding file {path:?}");
+        let mut segment = match AudioSegment::from_file(path) {
+            Ok(segment) => segment,
+            Err(e) => {
+                log::error!("failed to decode file '{path:?}': {e}");
+                return None;
+            }
+        };
 
-    for entry in dir {
-        let path = entry.unwrap().path();
-        if path.is_file() {
-            let Some(f) = std::fs::File::open(&path).ok() else {
-                log::error!("failed to open file '{path:?}'");
-                continue;
-            };
-            log::info!("decoding file {path:?}");
-            let Ok(mut segment) = AudioSegment::from_media_source(Box::new(f)) else {
-                log::error!("failed to decode file '{path:?}'");
-                continue;
-            };
+        let filename = path.file_name().unwrap().to_str().unwrap().to_string();
 
-            let filename = path.file_name().unwrap().to_str().unwrap().to_string();
+        segment.resample(sample_rate);
+        segment.make_pitch_table(pitch.from, pitch.to, pitch.step);
+        Some(AudioFile::new(segment, filename))
+    };
+    #[cfg(feature = "parallel")]
+    let mut segments: Vec<AudioFile> = {
+        use rayon::prelude::*;
+        paths.par_iter().filter_map(decode).collect()
+    };
+    #[cfg(not(feature = "parallel"))]
+    let mut segments: Vec<AudioFile> = paths.iter().filter_map(decode).collect();
 
-            segment.resample(sample_rate);
-            segment.make_pitch_table(pitch.from, pitch.to, pitch.step);
-            segments.push(AudioFile::new(segment, filename));
-        }
-    }
+    // directory order is platform-dependent, keep the click selection deterministic
+    segments.sort_by(|a, b| a.filename.cmp(&b.filename));
     segments
 }
 
@@ -552,6 +651,23 @@ impl Clickpack {
         longest
     }
 
+    /// Returns all files with a peak amplitude above `threshold`, loudest first.
+    pub fn clipping_files(&self, threshold: f32) -> Vec<&AudioFile> {
+        let mut files: Vec<&AudioFile> = [
+            &self.player1,
+            &self.player2,
+            &self.left1,
+            &self.right1,
+            &self.left2,
+            &self.right2,
+        ]
+        .into_iter()
+        .flat_map(|clicks| clicks.clipping_files(threshold))
+        .collect();
+        files.sort_by(|a, b| b.peak_amplitude().total_cmp(&a.peak_amplitude()));
+        files
+    }
+
     pub fn num_sounds(&self) -> usize {
         self.player1.num_sounds()
             + self.player2.num_sounds()
@@ -564,6 +680,19 @@ impl Clickpack {
 
 const CLICKPACK_DIRNAMES: [&str; 6] = ["player1", "player2", "left1", "left2", "right1", "right2"];
 
+/// Render statistics computed by [`Bot::dry_run`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DryRunStats {
+    /// Number of actions that will be mixed.
+    pub action_count: usize,
+    /// Number of sounds in the loaded clickpack.
+    pub total_sounds: usize,
+    /// Estimated peak memory usage of mixing all clicks (in megabytes).
+    pub estimated_memory_mb: f64,
+    /// Expected duration of the output.
+    pub estimated_duration: Duration,
+}
+
 #[derive(Default)]
 pub struct Bot {
     /// Clicks/releases for player 1 and player 2.
@@ -578,6 +707,8 @@ pub struct Bot {
     pub ns: BTreeMap<String, f64>,
     slab: fasteval2::Slab,
     pub compiled_expr: fasteval2::Instruction,
+    /// RNG for click selection, pitch and random volume, see [`Bot::with_seed`].
+    rng: fastrand::Rng,
 }
 
 pub fn find_noise_file(dir: &Path) -> Option<PathBuf> {
@@ -625,6 +756,13 @@ impl Bot {
         }
     }
 
+    /// Seeds the RNG used for click selection, pitch and random volume, so rendering the
+    /// same replay with the same seed always gives the same output.
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        self.rng = fastrand::Rng::with_seed(seed);
+        self
+    }
+
     #[inline]
     pub const fn has_noise(&self) -> bool {
         self.noise.is_some()
@@ -637,7 +775,7 @@ impl Bot {
         for (i, dir) in CLICKPACK_DIRNAMES.iter().enumerate() {
             let mut path = clickpack_dir.to_path_buf();
             path.push(dir);
-            self.clickpack[i] = PlayerClicks::from_path(&path, pitch, self.sample_rate);
+            self.clickpack[i].reload_from_path(&path, pitch, self.sample_rate);
 
             // try to load noise from the sound directories
             if !self.has_noise() {
@@ -647,7 +785,7 @@ impl Bot {
 
         if !self.has_clicks() {
             log::warn!("folders {CLICKPACK_DIRNAMES:?} were not found in the clickpack, assuming there is only one player");
-            self.clickpack[0] = PlayerClicks::from_path(&clickpack_dir, pitch, self.sample_rate);
+            self.clickpack[0].reload_from_path(&clickpack_dir, pitch, self.sample_rate);
         }
 
         // find longest click (will be used to ensure that the end doesn't get cut off)
@@ -672,10 +810,7 @@ impl Bot {
         let Some(path) = find_noise_file(dir) else {
             return;
         };
-        let Ok(f) = std::fs::File::open(path) else {
-            return;
-        };
-        self.noise = if let Ok(mut noise) = AudioSegment::from_media_source(Box::new(f)) {
+        self.noise = if let Ok(mut noise) = AudioSegment::from_file(&path) {
             noise.resample(self.sample_rate);
             Some(noise)
         } else {
@@ -683,7 +818,14 @@ impl Bot {
         };
     }
 
-    fn get_random_click(&mut self, player: Player, click: Click) -> &AudioSegment {
+    /// Picks a random sound for `click`, with a random pitch from the pitch table if
+    /// `enable_pitch` is set.
+    fn get_random_click(
+        &mut self,
+        player: Player,
+        click: Click,
+        enable_pitch: bool,
+    ) -> &AudioSegment {
         // try to get a random click/release from the player clicks
         // if it doesn't exist for the wanted player, use the other one (guaranteed to have atleast
         // one click)
@@ -693,24 +835,25 @@ impl Bot {
         let r1 = &self.clickpack.right1;
         let l2 = &self.clickpack.left2;
         let r2 = &self.clickpack.right2;
+        let rng = &mut self.rng;
 
         // :tired_face:
         macro_rules! random_click_ord {
             ($typ:ident, $one:ident, $two:ident, $three:ident, $four:ident, $five:ident, $six: ident) => {
-                $one.random_click($typ).unwrap_or_else(|| {
-                    $two.random_click($typ).unwrap_or_else(|| {
-                        $three.random_click($typ).unwrap_or_else(|| {
-                            $four.random_click($typ).unwrap_or_else(|| {
+                $one.random_click($typ, rng).unwrap_or_else(|| {
+                    $two.random_click($typ, rng).unwrap_or_else(|| {
+                        $three.random_click($typ, rng).unwrap_or_else(|| {
+                            $four.random_click($typ, rng).unwrap_or_else(|| {
                                 $five
-                                    .random_click($typ)
-                                    .unwrap_or_else(|| $six.random_click($typ).unwrap())
+                                    .random_click($typ, rng)
+                                    .unwrap_or_else(|| $six.random_click($typ, rng).unwrap())
                             })
                         })
                     })
                 })
             };
         }
-        match click {
+        let sound = match click {
             Click::Regular(typ) => {
                 if player == Player::One {
                     random_click_ord!(typ, p1, p2, l1, r1, l2, r2)
@@ -732,6 +875,12 @@ impl Bot {
                     random_click_ord!(typ, r2, l2, p2, r1, l1, p1)
                 }
             }
+        };
+        if enable_pitch {
+            // if no pitch table is generated, returns the sound itself
+            sound.random_pitch(rng)
+        } else {
+            sound
         }
     }
 
@@ -749,20 +898,18 @@ impl Bot {
     }
 
     /// Updates the volume variation expressions' namespace.
-    pub fn update_namespace(
-        &mut self,
-        a: &ExtendedAction,
-        prev_frame: u32,
-        total_frames: u32,
-        fps: f64,
-    ) {
+    pub fn update_namespace(&mut self, a: &ExtendedAction, prev_frame: u32, replay: &Replay) {
+        let total_frames = replay.last_frame();
+        let fps = replay.fps;
         self.ns.insert("frame".to_string(), a.frame as _);
         self.ns.insert("fps".to_string(), fps);
         self.ns.insert("time".to_string(), a.frame as f64 / fps);
         self.ns.insert("x".to_string(), a.x as _);
         self.ns.insert("y".to_string(), a.y as _);
-        self.ns
-            .insert("p".to_string(), a.frame as f64 / total_frames as f64);
+        self.ns.insert(
+            "p".to_string(),
+            replay.approximate_level_percentage(a.frame),
+        );
         self.ns.insert("player2".to_string(), a.player2 as u8 as _);
         self.ns.insert("rot".to_string(), a.rot as _);
         self.ns.insert("accel".to_string(), a.y_accel as _);
@@ -770,9 +917,11 @@ impl Bot {
         self.ns.insert("frames".to_string(), total_frames as _);
         self.ns
             .insert("level_time".to_string(), total_frames as f64 / fps);
-        self.ns.insert("rand".to_string(), fastrand::f64());
-        self.ns
-            .insert("delta".to_string(), (a.frame - prev_frame) as f64);
+        self.ns.insert("rand".to_string(), self.rng.f64());
+        self.ns.insert(
+            "delta".to_string(),
+            a.frame.saturating_sub(prev_frame) as f64,
+        );
     }
 
     pub fn eval_expr(&mut self) -> Result<f64> {
@@ -787,7 +936,7 @@ impl Bot {
         let mut max = f64::MIN;
         let mut prev_frame = 0u32;
         for action in &replay.extended {
-            self.update_namespace(action, prev_frame, replay.last_frame(), replay.fps.into());
+            self.update_namespace(action, prev_frame, replay);
             prev_frame = action.frame;
 
             let val = self.eval_expr().unwrap_or(0.);
@@ -808,6 +957,36 @@ impl Bot {
         enable_pitch: bool,
         cut_sounds: bool,
     ) -> AudioSegment {
+        self.render_replay_cancellable(
+            replay,
+            noise,
+            noise_volume,
+            normalize,
+            expr_var,
+            enable_pitch,
+            cut_sounds,
+            &AtomicBool::new(false),
+            |_| (),
+        )
+        .unwrap()
+    }
+
+    /// Same as [`Bot::render_replay`], but calls `on_progress` with the number of
+    /// rendered actions after every action, and stops and returns `None` as soon as
+    /// `cancel` is set. Used for rendering on a background thread.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_replay_cancellable(
+        &mut self,
+        replay: &Replay,
+        noise: bool,
+        noise_volume: f32,
+        normalize: bool,
+        expr_var: ExprVariable,
+        enable_pitch: bool,
+        cut_sounds: bool,
+        cancel: &AtomicBool,
+        mut on_progress: impl FnMut(usize),
+    ) -> Option<AudioSegment> {
         log::info!(
             "starting render, {} actions, noise: {noise}",
             replay.actions.len()
@@ -825,30 +1004,23 @@ impl Bot {
         );
         let start = Instant::now();
         let mut prev_frame = 0u32;
+        // only pan the players apart if there are two of them
+        let two_player = replay.actions.iter().any(|a| a.player == Player::Two);
 
         for (i, action) in replay.actions.iter().enumerate() {
+            if cancel.load(Ordering::Relaxed) {
+                log::info!("render cancelled after {i} actions");
+                return None;
+            }
+
             // calculate the volume from the expression if needed
             let (expr_vol, time_offset) = if expr_var != ExprVariable::None {
-                // get extended action
-                // FIXME: this is very wasteful, currently we binary search the entire
-                //        actions array each time
-                let extended = replay
-                    .extended
-                    .binary_search_by(|a| a.frame.cmp(&action.frame))
-                    .unwrap_or(usize::MAX);
-                let extended = replay
-                    .extended
-                    .get(extended)
-                    .copied()
-                    .unwrap_or(ExtendedAction::default());
+                // get (interpolated) extended action, so positions don't jump between
+                // actions that are far apart
+                let extended = replay.sample_extended_at(action.time, action.player);
 
                 // compute expression
-                self.update_namespace(
-                    &extended,
-                    prev_frame,
-                    replay.last_frame(),
-                    replay.fps.into(),
-                );
+                self.update_namespace(&extended, prev_frame, replay);
                 prev_frame = extended.frame;
 
                 let value = self.eval_expr().unwrap_or(0.0) as f32;
@@ -858,9 +1030,11 @@ impl Bot {
                         if value == 0.0 {
                             (0.0, 0.0)
                         } else if negative {
-                            (f32_range((-value).min(value)..=value.max(-value)), 0.0)
+                            let range = (-value).min(value)..=value.max(-value);
+                            (rng_f32_range(&mut self.rng, range), 0.0)
                         } else {
-                            (f32_range(value.min(0.0)..=value.max(0.0)), 0.0)
+                            let range = value.min(0.0)..=value.max(0.0);
+                            (rng_f32_range(&mut self.rng, range), 0.0)
                         }
                     }
                     ExprVariable::TimeOffset => (0.0, value),
@@ -870,10 +1044,7 @@ impl Bot {
                 (0.0, 0.0)
             };
 
-            let mut click = self.get_random_click(action.player, action.click);
-            if enable_pitch {
-                click = click.random_pitch(); // if no pitch table is generated, returns self
-            }
+            let click = self.get_random_click(action.player, action.click, enable_pitch);
 
             let mut until_next = f64::INFINITY;
             if cut_sounds {
@@ -888,27 +1059,23 @@ impl Bot {
             }
 
             // overlay
-            segment.overlay_at_vol(
+            let volume = 1.0 + action.vol_offset + expr_vol;
+            let (left, right) = if two_player {
+                replay.vol_settings.stereo_volumes(action.player)
+            } else {
+                (1.0, 1.0)
+            };
+            segment.overlay_at_stereo_vol(
                 action.time + time_offset as f64,
                 click,
-                1.0 + action.vol_offset + expr_vol,
+                (volume * left, volume * right),
                 until_next,
             );
+            on_progress(i + 1);
         }
 
-        if noise && self.has_noise() {
-            let mut noise_duration = Duration::from_secs(0);
-            let noise_segment = self.noise.as_ref().unwrap();
-
-            while noise_duration < segment.duration() {
-                segment.overlay_at_vol(
-                    noise_duration.as_secs_f64(),
-                    noise_segment,
-                    noise_volume,
-                    f64::INFINITY, // don't cut off
-                );
-                noise_duration += noise_segment.duration();
-            }
+        if noise {
+            self.overlay_noise(&mut segment, noise_volume);
         }
 
         if normalize {
@@ -916,7 +1083,316 @@ impl Bot {
         }
 
         log::info!("rendered in {:?}", start.elapsed());
-        segment
+        Some(segment)
+    }
+
+    /// Loops the noise sound over the whole `segment`. Does nothing if the clickpack has
+    /// no noise.
+    fn overlay_noise(&self, segment: &mut AudioSegment, noise_volume: f32) {
+        let Some(noise_segment) = &self.noise else {
+            return;
+        };
+        let mut noise_duration = Duration::from_secs(0);
+        while noise_duration < segment.duration() {
+            segment.overlay_at_vol(
+                noise_duration.as_secs_f64(),
+                noise_segment,
+                noise_volume,
+                f64::INFINITY, // don't cut off
+            );
+            noise_duration += noise_segment.duration();
+        }
+    }
+
+    /// Renders `replay` with the noise ducked by every click, like a sidechain compressor.
+    /// At each click the noise drops to `sidechain_ratio` of its volume (0 mutes it,
+    /// 1 disables ducking) and linearly recovers over 50 ms.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_with_sidechain_compression(
+        &mut self,
+        replay: &Replay,
+        noise_volume: f32,
+        sidechain_ratio: f32,
+        normalize: bool,
+        expr_var: ExprVariable,
+        enable_pitch: bool,
+        cut_sounds: bool,
+    ) -> Result<AudioSegment> {
+        /// Time it takes for the noise to recover after a click, in seconds.
+        const RELEASE_TIME: f64 = 0.05;
+
+        if !self.has_noise() {
+            anyhow::bail!("the clickpack has no noise file");
+        }
+        let mut segment = self.render_replay(
+            replay,
+            false,
+            0.0,
+            false,
+            expr_var,
+            enable_pitch,
+            cut_sounds,
+        );
+        let mut noise = AudioSegment {
+            sample_rate: segment.sample_rate,
+            frames: vec![Frame::ZERO; segment.frames.len()],
+            pitch_table: vec![],
+        };
+        self.overlay_noise(&mut noise, noise_volume);
+
+        // gain of the noise at every frame
+        let duck = 1.0 - sidechain_ratio.clamp(0.0, 1.0);
+        let release_frames = (RELEASE_TIME * segment.sample_rate as f64) as usize;
+        let mut envelope = vec![1.0f32; noise.frames.len()];
+        for action in replay.actions.iter().filter(|a| a.click.is_click()) {
+            let onset = (action.time.max(0.0) * segment.sample_rate as f64) as usize;
+            let end = (onset + release_frames).min(envelope.len());
+            for (i, gain) in envelope.iter_mut().enumerate().take(end).skip(onset) {
+                let recovered = (i - onset) as f32 / release_frames as f32;
+                *gain = gain.min(1.0 - duck * (1.0 - recovered));
+            }
+        }
+
+        for ((frame, noise), gain) in segment.frames.iter_mut().zip(&noise.frames).zip(envelope) {
+            *frame += *noise * gain;
+        }
+        if normalize {
+            segment.normalize();
+        }
+        Ok(segment)
+    }
+
+    /// Renders `replay` and mixes it into `target`, starting `time_offset_secs` into it.
+    /// `target` is extended if the rendered audio doesn't fit. Used for compositing
+    /// multiple replays into one output. Fails if `target` already has audio at a
+    /// different sample rate than the bot.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_replay_into(
+        &mut self,
+        replay: &Replay,
+        target: &mut AudioSegment,
+        time_offset_secs: f64,
+        noise: bool,
+        noise_volume: f32,
+        normalize: bool,
+        expr_var: ExprVariable,
+        enable_pitch: bool,
+        cut_sounds: bool,
+    ) -> Result<()> {
+        if !target.frames.is_empty() && target.sample_rate != self.sample_rate {
+            anyhow::bail!(
+                "the composite is at {} Hz, but the clickpack is loaded at {} Hz",
+                target.sample_rate,
+                self.sample_rate
+            );
+        }
+        let segment = self.render_replay(
+            replay,
+            noise,
+            noise_volume,
+            normalize,
+            expr_var,
+            enable_pitch,
+            cut_sounds,
+        );
+        if target.frames.is_empty() {
+            target.sample_rate = segment.sample_rate;
+        }
+        let time_offset_secs = time_offset_secs.max(0.0);
+        let end = (time_offset_secs * target.sample_rate as f64) as usize + segment.frames.len();
+        if target.frames.len() <= end {
+            // `overlay_at` never writes the last frame
+            target.frames.resize(end + 1, Frame::ZERO);
+        }
+        target.overlay_at(time_offset_secs, &segment);
+        Ok(())
+    }
+
+    /// Renders every click type of `replay` into its own WAV file in `output_dir`, named
+    /// after the click type (e.g. `hardclicks.wav`), for mixing them separately. All stems
+    /// have the same length. Noise and normalization are not applied, so that the stems
+    /// keep their relative volume. Returns the paths of the stems in
+    /// [`ClickType::to_index`] order.
+    pub fn render_stems(
+        &mut self,
+        replay: &Replay,
+        output_dir: &Path,
+        expr_var: ExprVariable,
+        enable_pitch: bool,
+        cut_sounds: bool,
+    ) -> Result<[PathBuf; ClickType::COUNT]> {
+        std::fs::create_dir_all(output_dir)?;
+        let mut paths: [PathBuf; ClickType::COUNT] = Default::default();
+        for (i, path) in paths.iter_mut().enumerate() {
+            let typ = ClickType::from_index(i);
+            let mut stem = replay.clone();
+            stem.actions.retain(|a| a.click.click_type() == typ);
+
+            let segment =
+                self.render_replay(&stem, false, 0.0, false, expr_var, enable_pitch, cut_sounds);
+            *path = output_dir.join(format!("{}.wav", typ.dir_name()));
+            log::info!("writing {typ:?} stem to {path:?}");
+            segment.export_wav(std::fs::File::create(&*path)?)?;
+        }
+        Ok(paths)
+    }
+
+    /// Renders `replay` with a Lua script deciding the click type, volume and pitch of
+    /// every action, instead of the default classification and volume calculation.
+    ///
+    /// The script gets a global `actions` array, where each action has the `time`,
+    /// `frame`, `player` (1 or 2), `down`, `click_type` and `volume` fields. It has to
+    /// return an array with an override table for every action, for example
+    /// `{ volume = 1.0, pitch = 1.0, click_type = "HardClick" }`. Missing entries and
+    /// fields keep their default values. Only the base, `math`, `string` and `table`
+    /// libraries are available to the script.
+    #[cfg(feature = "lua")]
+    pub fn run_script(
+        &mut self,
+        replay: &Replay,
+        script: &str,
+        noise: bool,
+        noise_volume: f32,
+        normalize: bool,
+    ) -> Result<AudioSegment> {
+        use anyhow::Context;
+        use mlua::{Lua, LuaOptions, StdLib, Table};
+
+        let lua = Lua::new_with(
+            StdLib::MATH | StdLib::STRING | StdLib::TABLE,
+            LuaOptions::default(),
+        )?;
+        let actions = lua.create_table()?;
+        for (i, action) in replay.actions.iter().enumerate() {
+            let table = lua.create_table()?;
+            table.set("time", action.time)?;
+            table.set("frame", action.frame)?;
+            table.set("player", if action.player == Player::One { 1 } else { 2 })?;
+            table.set("down", action.click.is_click())?;
+            table.set("click_type", format!("{:?}", action.click.click_type()))?;
+            table.set("volume", 1.0 + action.vol_offset)?;
+            actions.set(i + 1, table)?; // lua arrays start at 1
+        }
+        lua.globals().set("actions", actions)?;
+        let overrides: Table = lua
+            .load(script)
+            .set_name("script")
+            .eval()
+            .context("the script has to return a table")?;
+
+        let mut segment =
+            AudioSegment::silent(self.sample_rate, replay.duration + self.longest_click);
+        for (i, action) in replay.actions.iter().enumerate() {
+            let (mut click, mut volume, mut pitch) = (action.click, 1.0 + action.vol_offset, None);
+            if let Some(entry) = overrides.get::<_, Option<Table>>(i + 1)? {
+                if let Some(name) = entry.get::<_, Option<String>>("click_type")? {
+                    let typ = (0..ClickType::COUNT)
+                        .map(ClickType::from_index)
+                        .find(|typ| format!("{typ:?}").eq_ignore_ascii_case(&name))
+                        .with_context(|| format!("unknown click type '{name}'"))?;
+                    click = Click::from_button(action.click.button(), typ);
+                }
+                volume = entry.get::<_, Option<f32>>("volume")?.unwrap_or(volume);
+                pitch = entry.get::<_, Option<f32>>("pitch")?;
+            }
+
+            let sample_rate = self.sample_rate;
+            let sound = self.get_random_click(action.player, click, false);
+            match pitch {
+                Some(pitch) if pitch > 0.0 && pitch != 1.0 => {
+                    let mut pitched = AudioSegment {
+                        sample_rate,
+                        frames: sound.frames.clone(),
+                        pitch_table: vec![],
+                    };
+                    pitched.resample((sample_rate as f32 * pitch) as u32);
+                    pitched.sample_rate = sample_rate; // keep same sample rate
+                    segment.overlay_at_vol(action.time, &pitched, volume, f64::INFINITY);
+                }
+                _ => segment.overlay_at_vol(action.time, sound, volume, f64::INFINITY),
+            }
+        }
+        if noise {
+            self.overlay_noise(&mut segment, noise_volume);
+        }
+        if normalize {
+            segment.normalize();
+        }
+        Ok(segment)
+    }
+
+    /// Returns a copy of `replay` with correlated, human-like variations. Action times are
+    /// offset along a sine wave over the whole replay plus a bit of random jitter, and the
+    /// volume slowly drifts with a random walk. `strength` is in the range of 0 to 1. The
+    /// frames of the actions are moved with their times. Uses the bot's RNG, see
+    /// [`Bot::with_seed`].
+    pub fn apply_humanisation_pass(&mut self, replay: &Replay, strength: f32) -> Replay {
+        /// Largest time offset at full strength, in seconds.
+        const MAX_TIME_OFFSET: f64 = 0.004;
+        /// Largest random time jitter at full strength, in seconds.
+        const MAX_TIME_JITTER: f64 = 0.001;
+        /// Largest volume change between two actions at full strength.
+        const VOLUME_STEP: f32 = 0.02;
+        /// The volume never drifts further than this at full strength.
+        const MAX_VOLUME_DRIFT: f32 = 0.2;
+
+        let strength = strength.clamp(0.0, 1.0);
+        let mut replay = replay.clone();
+        let num_actions = replay.actions.len().max(1) as f64;
+        let mut drift = 0.0f32;
+        for (i, action) in replay.actions.iter_mut().enumerate() {
+            let wave = (std::f64::consts::TAU * i as f64 / num_actions).sin();
+            let offset = strength as f64
+                * (MAX_TIME_OFFSET * wave + MAX_TIME_JITTER * (self.rng.f64() * 2.0 - 1.0));
+            action.time = (action.time + offset).max(0.0);
+            if replay.fps > 0.0 {
+                action.frame = (action.time * replay.fps).round() as u32;
+            }
+
+            drift = (drift + rng_f32_range(&mut self.rng, -VOLUME_STEP..=VOLUME_STEP) * strength)
+                .clamp(-MAX_VOLUME_DRIFT * strength, MAX_VOLUME_DRIFT * strength);
+            action.vol_offset += drift;
+        }
+        // jitter may swap actions that are very close to each other
+        replay.actions.sort_by(|a, b| a.time.total_cmp(&b.time));
+        replay
+    }
+
+    /// Writes a plain-text list of action timestamps (`time_secs,click_type,player`), sorted
+    /// by time. Can be imported as markers in video editors.
+    pub fn export_action_timestamps<W: Write>(replay: &Replay, mut writer: W) -> Result<()> {
+        writeln!(writer, "time_secs,click_type,player")?;
+        let mut actions: Vec<_> = replay.actions.iter().collect();
+        actions.sort_by(|a, b| a.time.total_cmp(&b.time));
+        for action in actions {
+            writeln!(
+                writer,
+                "{:.6},{:?},{}",
+                action.time,
+                action.click.click_type(),
+                if action.player == Player::One { 1 } else { 2 }
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Computes render statistics for a replay without producing any audio.
+    pub fn dry_run(&self, replay: &Replay) -> DryRunStats {
+        const CHANNELS: f64 = 2.0;
+        let action_count = replay.actions.len();
+        let memory_bytes = self.longest_click
+            * action_count as f64
+            * self.sample_rate as f64
+            * CHANNELS
+            * std::mem::size_of::<f32>() as f64;
+        DryRunStats {
+            action_count,
+            total_sounds: self.clickpack.num_sounds(),
+            estimated_memory_mb: memory_bytes / (1024.0 * 1024.0),
+            estimated_duration: Duration::from_secs_f64(
+                (replay.duration + self.longest_click).max(0.0),
+            ),
+        }
     }
 
     #[inline]
@@ -973,6 +1449,17 @@ impl Bot {
                         click.set_volume(settings.volume);
                     }
 
+                    // pitch shift
+                    if let Some(ratio) = settings.pitch_shift.filter(|&r| r > 0.0 && r != 1.0) {
+                        click.pitch_shift(ratio);
+                    }
+
+                    // high-pass filter
+                    if let Some(cutoff) = settings.high_pass_cutoff_hz.filter(|&c| c > 0.0) {
+                        let sample_rate = click.sample_rate;
+                        click.apply_high_pass_filter(cutoff, sample_rate);
+                    }
+
                     // reverse
                     if settings.reverse {
                         click.reverse();
@@ -991,6 +1478,14 @@ impl Bot {
                         }
                     }
 
+                    // fade in/out, after removing silence so the fades are audible
+                    if settings.fade_in_ms != 0 {
+                        click.apply_fade_in(Duration::from_millis(settings.fade_in_ms as u64));
+                    }
+                    if settings.fade_out_ms != 0 {
+                        click.apply_fade_out(Duration::from_millis(settings.fade_out_ms as u64));
+                    }
+
                     // create click file
                     if settings.rename_files {
                         player_path.push(format!("{}.wav", i + 1));
@@ -1009,7 +1504,12 @@ impl Bot {
 
                     // export wave file
                     log::debug!("exporting wav file to {player_path:?}");
-                    click.export_wav(f)?;
+                    let channels = if settings.force_mono {
+                        1
+                    } else {
+                        click.channel_count() as u16
+                    };
+                    click.export_wav_channels(f, channels)?;
                     player_path.pop();
                 }
                 player_path.pop();
@@ -1033,3 +1533,82 @@ impl Bot {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Action;
+
+    #[test]
+    fn test_seeded_humanisation_pass() {
+        let mut replay = Replay::default();
+        replay.fps = 240.0;
+        replay.actions = (0..50)
+            .map(|i| {
+                let frame = 240 + i * 7;
+                let click = Click::Regular(ClickType::Click);
+                Action::new(frame as f64 / 240.0, Player::One, click, 0.0, frame)
+            })
+            .collect();
+        let humanise = |seed| {
+            let mut bot = Bot::default();
+            bot.with_seed(seed);
+            bot.apply_humanisation_pass(&replay, 1.0)
+        };
+        let (a, b) = (humanise(7), humanise(7));
+        for (a, b) in a.actions.iter().zip(&b.actions) {
+            assert_eq!(
+                (a.time, a.vol_offset, a.frame),
+                (b.time, b.vol_offset, b.frame)
+            );
+            assert_eq!(a.frame, (a.time * 240.0).round() as u32);
+        }
+        assert!(a
+            .actions
+            .iter()
+            .zip(&replay.actions)
+            .any(|(a, orig)| a.time != orig.time));
+    }
+
+    #[test]
+    #[cfg(feature = "lua")]
+    fn test_run_script() {
+        let sound = |value, len| AudioSegment {
+            sample_rate: 100,
+            frames: vec![Frame::from_mono(value); len],
+            pitch_table: vec![],
+        };
+        let mut bot = Bot::new(100);
+        bot.clickpack
+            .player1
+            .clicks
+            .push(AudioFile::new(sound(0.5, 10), "click".to_string()));
+        bot.longest_click = 0.1;
+        bot.noise = Some(sound(0.1, 50));
+
+        let mut replay = Replay::default();
+        replay.duration = 1.0;
+        replay.actions = vec![Action::new(
+            0.5,
+            Player::One,
+            Click::Regular(ClickType::Click),
+            0.0,
+            120,
+        )];
+        let script = r#"
+            assert(io == nil and os == nil, "unsafe libraries are loaded")
+            return { { volume = math.min(2, 4) } }
+        "#;
+
+        let segment = bot.run_script(&replay, script, false, 1.0, false).unwrap();
+        assert_eq!(segment.frames[50].left, 1.0); // 0.5 at twice the volume
+        assert_eq!(segment.frames[0].left, 0.0);
+
+        let segment = bot.run_script(&replay, script, true, 1.0, false).unwrap();
+        assert!((segment.frames[0].left - 0.1).abs() < 1e-6);
+
+        let segment = bot.run_script(&replay, script, true, 1.0, true).unwrap();
+        assert!((segment.frames[50].left - 1.0).abs() < 1e-6);
+        assert!((segment.frames[0].left - 0.1 / 1.1).abs() < 1e-6);
+    }
+}
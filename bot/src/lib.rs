@@ -5,7 +5,10 @@ mod parser;
 pub use audio::*;
 pub use bot::*;
 pub use fasteval2;
+pub use gdr;
 pub use parser::*;
+#[cfg(feature = "graph")]
+pub use petgraph;
 
 use std::ops::RangeInclusive;
 
@@ -18,3 +21,9 @@ pub fn f32_range(range: RangeInclusive<f32>) -> f32 {
 pub fn f64_range(range: RangeInclusive<f64>) -> f64 {
     fastrand::f64() * (range.end() - range.start()) + range.start()
 }
+
+/// Same as [`f32_range`], but uses `rng` instead of the global RNG.
+#[inline]
+pub fn rng_f32_range(rng: &mut fastrand::Rng, range: RangeInclusive<f32>) -> f32 {
+    rng.f32() * (range.end() - range.start()) + range.start()
+}
@@ -1,13 +1,22 @@
-use crate::{f32_range, Timings, VolumeSettings};
+use crate::{rng_f32_range, Bot, ClassificationMode, Timings, VolumeSettings};
 use anyhow::{Context, Result};
 use byteorder::{LittleEndian, ReadBytesExt};
 use ijson::IValue;
 use serde::Deserialize;
 use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    collections::{HashMap, HashSet},
+    io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    ops::RangeInclusive,
+    path::Path,
 };
 
+/// Actions this many frames apart are considered simultaneous, see
+/// [`Replay::group_simultaneous_actions`].
+pub const SIMULTANEOUS_TOLERANCE_FRAMES: u32 = 1;
+
+/// JSON Schema of the GDR JSON written by [`Replay::export_as_json_schema_validated`].
+pub const GDR_SCHEMA: &str = include_str!("gdr_schema.json");
+
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub enum ClickType {
     HardClick,
@@ -33,8 +42,9 @@ impl ClickType {
         timings: Timings,
         is_click: bool,
         vol: VolumeSettings,
+        rng: &mut fastrand::Rng,
     ) -> (Self, f32) {
-        let rand_var = f32_range(-vol.volume_var..=vol.volume_var);
+        let rand_var = rng_f32_range(rng, -vol.volume_var..=vol.volume_var);
         let vol_offset =
             if vol.enabled && time < vol.spam_time && !(!vol.change_releases_volume && !is_click) {
                 let offset = (vol.spam_time - time) as f32 * vol.spam_vol_offset_factor;
@@ -43,19 +53,38 @@ impl ClickType {
                 rand_var * vol.global_volume
             };
 
-        let typ = if time > timings.hard {
+        (Self::from_timings(time, timings, is_click), vol_offset)
+    }
+
+    /// Classifies a click by the Y velocity of the player. Higher velocity means a harder
+    /// click, the timings are used as velocity thresholds.
+    ///
+    /// # Returns
+    ///
+    /// The click type (always a click, use [`ClickType::inverted`] for releases) and
+    /// the velocity magnitude.
+    pub fn from_velocity(y_vel: f32, timings: Timings) -> (Self, f32) {
+        let magnitude = y_vel.abs();
+        (
+            Self::from_timings(magnitude as f64, timings, true),
+            magnitude,
+        )
+    }
+
+    fn from_timings(value: f64, timings: Timings, is_click: bool) -> Self {
+        if value > timings.hard {
             if is_click {
                 Self::HardClick
             } else {
                 Self::HardRelease
             }
-        } else if time > timings.regular {
+        } else if value > timings.regular {
             if is_click {
                 Self::Click
             } else {
                 Self::Release
             }
-        } else if time > timings.soft {
+        } else if value > timings.soft {
             if is_click {
                 Self::SoftClick
             } else {
@@ -65,8 +94,61 @@ impl ClickType {
             Self::MicroClick
         } else {
             Self::MicroRelease
-        };
-        (typ, vol_offset)
+        }
+    }
+
+    /// Number of click types, excluding [`ClickType::None`].
+    pub const COUNT: usize = 8;
+
+    /// Returns the index of the click type in arrays of click types, such as
+    /// [`PlayerClicks`](crate::PlayerClicks). [`ClickType::None`] maps to
+    /// [`ClickType::COUNT`], which is not a valid index.
+    pub const fn to_index(self) -> usize {
+        use ClickType::*;
+        match self {
+            HardClick => 0,
+            HardRelease => 1,
+            Click => 2,
+            Release => 3,
+            SoftClick => 4,
+            SoftRelease => 5,
+            MicroClick => 6,
+            MicroRelease => 7,
+            None => Self::COUNT,
+        }
+    }
+
+    /// Inverse of [`ClickType::to_index`]. Out of range indices return [`ClickType::None`].
+    pub const fn from_index(idx: usize) -> Self {
+        use ClickType::*;
+        match idx {
+            0 => HardClick,
+            1 => HardRelease,
+            2 => Click,
+            3 => Release,
+            4 => SoftClick,
+            5 => SoftRelease,
+            6 => MicroClick,
+            7 => MicroRelease,
+            _ => None,
+        }
+    }
+
+    /// Name of the clickpack directory of this click type, e.g. `"hardclicks"`.
+    /// [`ClickType::None`] returns an empty string.
+    pub const fn dir_name(self) -> &'static str {
+        use ClickType::*;
+        match self {
+            HardClick => "hardclicks",
+            HardRelease => "hardreleases",
+            Click => "clicks",
+            Release => "releases",
+            SoftClick => "softclicks",
+            SoftRelease => "softreleases",
+            MicroClick => "microclicks",
+            MicroRelease => "microreleases",
+            None => "",
+        }
     }
 
     /// Order of which clicks should be selected depending on the actual click type
@@ -171,6 +253,22 @@ impl ClickType {
     pub const fn is_click(self) -> bool {
         !self.is_release()
     }
+
+    /// Returns the release type for a click type and vice versa.
+    pub const fn inverted(self) -> Self {
+        use ClickType::*;
+        match self {
+            HardClick => HardRelease,
+            HardRelease => HardClick,
+            Click => Release,
+            Release => Click,
+            SoftClick => SoftRelease,
+            SoftRelease => SoftClick,
+            MicroClick => MicroRelease,
+            MicroRelease => MicroClick,
+            None => None,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -204,6 +302,24 @@ impl Click {
         self.click_type().is_release()
     }
 
+    /// Returns the button index of this click (1 = regular, 2 = left, 3 = right).
+    pub const fn button(self) -> i32 {
+        match self {
+            Click::Regular(_) => 1,
+            Click::Left(_) => 2,
+            Click::Right(_) => 3,
+        }
+    }
+
+    /// Creates a click from a button index. Unknown indices are treated as regular clicks.
+    pub const fn from_button(button: i32, typ: ClickType) -> Self {
+        match button {
+            3 => Self::Right(typ),
+            2 => Self::Left(typ),
+            _ => Self::Regular(typ),
+        }
+    }
+
     const fn from_button_and_typ(button: Button, typ: ClickType) -> Self {
         match button {
             Button::Push | Button::Release => Self::Regular(typ),
@@ -319,6 +435,60 @@ pub struct ExtendedAction {
     pub fps_change: Option<f64>,
 }
 
+/// Kind of a [`GameEvent`], as emitted by GD event recorders (e.g. `SPEED_PORTAL`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum GameEventType {
+    OrbActivated,
+    PadActivated,
+    SpeedPortal,
+    GravityPortal,
+    GamemodePortal,
+    Checkpoint,
+}
+
+impl GameEventType {
+    /// RGB color of the event marker in visualisations.
+    pub const fn color(self) -> [u8; 3] {
+        match self {
+            Self::OrbActivated => [255, 220, 0],
+            Self::PadActivated => [255, 0, 220],
+            Self::SpeedPortal => [0, 255, 120],
+            Self::GravityPortal => [0, 200, 255],
+            Self::GamemodePortal => [255, 120, 0],
+            Self::Checkpoint => [120, 255, 0],
+        }
+    }
+}
+
+/// Game-side event that happened at a frame, see [`Replay::annotate_with_game_events`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub struct GameEvent {
+    pub frame: u32,
+    pub event_type: GameEventType,
+}
+
+/// Input counts of a single player, see [`Replay::input_stats_by_player`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerInputStats {
+    pub presses: usize,
+    pub releases: usize,
+    pub hardclicks: usize,
+    pub clicks: usize,
+    pub softclicks: usize,
+    pub microclicks: usize,
+}
+
+/// Sub-frame timing inconsistency of a replay, see [`Replay::jitter_analysis`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JitterStats {
+    pub max_jitter_frames: f64,
+    pub mean_jitter_frames: f64,
+    /// Number of actions per 0.1 frame jitter bucket. The last bucket also counts
+    /// everything above 1 frame.
+    pub jitter_histogram: Vec<u32>,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct Replay {
     /// Framerate of the replay.
@@ -338,11 +508,20 @@ pub struct Replay {
 
     // used for generating additional click info
     timings: Timings,
-    vol_settings: VolumeSettings,
+    /// Volume settings the replay was parsed with. [`VolumeSettings::stereo_separation`]
+    /// is applied when rendering, so it can be changed after parsing.
+    pub vol_settings: VolumeSettings,
 
     /// Whether to sort actions.
     sort_actions: bool,
     pub override_fps: Option<f64>,
+    classification_mode: ClassificationMode,
+    /// Minimum and maximum X positions, computed after parsing.
+    x_range: (f32, f32),
+    /// Game-side events, sorted by frame. See [`Replay::annotate_with_game_events`].
+    pub events: Vec<GameEvent>,
+    /// RNG for the random volume variation, see [`Replay::with_seed`].
+    rng: fastrand::Rng,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -404,6 +583,23 @@ pub enum ReplayType {
 }
 
 impl ReplayType {
+    /// Detects the replay format from the first bytes of the replay data. Only formats
+    /// with a magic number can be detected.
+    pub fn from_magic(data: &[u8]) -> Option<Self> {
+        use ReplayType::*;
+        Some(match data.get(..4)? {
+            b"HACK" => MhrBin,
+            b"META" => Echo,
+            b"RPLY" => ReplayBot,
+            b"DDHR" => Ddhor,
+            b"ybot" => Ybot2,
+            [b'R', b'E', b'2', _] => ReplayEngine2,
+            [b'Z', b'R', ..] => Zephyrus,
+            [0x1f, 0x8b, ..] => Rbot, // gzipped rbot replay
+            _ => return None,
+        })
+    }
+
     pub fn guess_format(filename: &str) -> Result<Self> {
         use ReplayType::*;
         let ext = filename
@@ -463,6 +659,18 @@ impl ReplayType {
 // }
 
 impl Replay {
+    /// Where [`GDR_SCHEMA`] is published, used as the `$schema` of exported GDR JSON.
+    pub const GDR_SCHEMA_URL: &'static str =
+        "https://raw.githubusercontent.com/zeozeozeo/zcb3/master/bot/src/gdr_schema.json";
+
+    /// Minimum X position drop that counts as a level restart.
+    pub const RESTART_X_THRESHOLD: f32 = 100.0;
+
+    /// Column names of [`Replay::to_sparse_matrix`].
+    pub const SPARSE_MATRIX_COLUMNS: [&'static str; 8] = [
+        "p1_down", "p1_up", "p2_down", "p2_up", "p1_left", "p1_right", "p2_left", "p2_right",
+    ];
+
     pub const SUPPORTED_EXTENSIONS: &'static [&'static str] = &[
         "json",
         "mhr.json",
@@ -523,6 +731,18 @@ impl Replay {
         self
     }
 
+    pub fn with_classification_mode(mut self, classification_mode: ClassificationMode) -> Self {
+        self.classification_mode = classification_mode;
+        self
+    }
+
+    /// Seeds the random volume variation, so parsing the same replay twice gives the
+    /// same volumes.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = fastrand::Rng::with_seed(seed);
+        self
+    }
+
     #[inline]
     pub fn has_actions(&self) -> bool {
         !self.actions.is_empty()
@@ -561,6 +781,10 @@ impl Replay {
             // MacroType::GatoBot => self.parse_gatobot(reader)?,
         }
 
+        if self.classification_mode == ClassificationMode::ByVelocity {
+            self.classify_by_velocity();
+        }
+
         // sort actions by time / frame
         if self.sort_actions {
             self.sort_actions();
@@ -570,6 +794,13 @@ impl Replay {
             self.duration = last.time;
         }
 
+        if self.vol_settings.simultaneous_click_boost != 0.0 {
+            self.boost_simultaneous_clicks();
+        }
+
+        let x_range = self.compute_x_range();
+        self.x_range = (*x_range.start(), *x_range.end());
+
         log::debug!(
             "replay fps: {}; replay duration: {:?}s",
             self.fps,
@@ -579,13 +810,89 @@ impl Replay {
         Ok(self)
     }
 
+    /// Same as [`Replay::parse`], but parses a replay that is already in memory.
+    pub fn parse_bytes(self, typ: ReplayType, data: &[u8]) -> Result<Self> {
+        self.parse(typ, Cursor::new(data))
+    }
+
+    /// Reads a replay from the standard input until EOF. The format is detected from the
+    /// data (see [`ReplayType::from_magic`]), `fallback` is used if that fails.
+    pub fn import_from_stdin(self, fallback: Option<ReplayType>) -> Result<Self> {
+        let mut data = vec![];
+        std::io::stdin().lock().read_to_end(&mut data)?;
+        let typ = ReplayType::from_magic(&data)
+            .or(fallback)
+            .context("couldn't detect the replay format, specify it explicitly")?;
+        self.parse_bytes(typ, &data)
+    }
+
+    /// Same as [`Replay::parse`], but memory-maps `file` instead of reading it through a
+    /// buffered reader. Faster for very large replays (e.g. long GDR recordings).
+    #[cfg(feature = "mmap")]
+    pub fn parse_memory_mapped(self, typ: ReplayType, file: &std::fs::File) -> Result<Self> {
+        // SAFETY: the file must not be modified by another process while it's being
+        // parsed, which would corrupt the replay no matter how it's read
+        let mmap = unsafe { memmap2::Mmap::map(file)? };
+        self.parse_bytes(typ, &mmap)
+    }
+
+    /// Reclassifies actions by the Y velocity of the player, see
+    /// [`ClickType::from_velocity`]. Actions without velocity data are left as is.
+    fn classify_by_velocity(&mut self) {
+        let velocities: HashMap<(u32, bool), f32> = self
+            .extended
+            .iter()
+            .map(|e| ((e.frame, e.player2), e.y_accel))
+            .collect();
+        for action in &mut self.actions {
+            let Some(&y_vel) = velocities.get(&(action.frame, action.player == Player::Two)) else {
+                continue;
+            };
+            if y_vel == 0.0 {
+                continue;
+            }
+            let (mut typ, _) = ClickType::from_velocity(y_vel, self.timings);
+            if !action.click.is_click() {
+                typ = typ.inverted();
+            }
+            action.click = Click::from_button(action.click.button(), typ);
+        }
+    }
+
+    /// Adds [`VolumeSettings::simultaneous_click_boost`] to the volume of every click that
+    /// happens together with another click.
+    fn boost_simultaneous_clicks(&mut self) {
+        let chord_clicks: HashSet<(u32, bool)> = self
+            .group_simultaneous_actions(SIMULTANEOUS_TOLERANCE_FRAMES)
+            .into_iter()
+            .filter(|group| group.iter().filter(|a| a.click.is_click()).count() >= 2)
+            .flatten()
+            .filter(|a| a.click.is_click())
+            .map(|a| (a.frame, a.player == Player::Two))
+            .collect();
+        let boost = self.vol_settings.simultaneous_click_boost * self.vol_settings.global_volume;
+        for action in &mut self.actions {
+            if action.click.is_click()
+                && chord_clicks.contains(&(action.frame, action.player == Player::Two))
+            {
+                action.vol_offset += boost;
+            }
+        }
+    }
+
     /// Sorts actions by time / frame.
     pub fn sort_actions(&mut self) -> &mut Self {
         self.actions.sort_by(|a, b| a.time.total_cmp(&b.time));
-        self.extended.sort_by(|a, b| a.frame.cmp(&b.frame));
+        self.sort_extended_stable();
         self
     }
 
+    /// Sorts the physics data by frame, with player 1 before player 2 on the same frame.
+    /// Entries of the same player and frame keep their relative order.
+    pub fn sort_extended_stable(&mut self) {
+        self.extended.sort_by_key(|e| (e.frame, e.player2 as u8));
+    }
+
     fn process_action_p1(&mut self, time: f64, button: Button, frame: u32) {
         let down = button.is_down();
         if !down && self.actions.is_empty() {
@@ -599,7 +906,8 @@ impl Replay {
         }
 
         let delta = time - self.prev_time.0;
-        let (typ, vol_offset) = ClickType::from_time(delta, self.timings, down, self.vol_settings);
+        let (typ, vol_offset) =
+            ClickType::from_time(delta, self.timings, down, self.vol_settings, &mut self.rng);
         // println!("ctyp: {typ:?}");
 
         self.prev_time.0 = time;
@@ -626,7 +934,8 @@ impl Replay {
         }
 
         let delta = time - self.prev_time.1;
-        let (typ, vol_offset) = ClickType::from_time(delta, self.timings, down, self.vol_settings);
+        let (typ, vol_offset) =
+            ClickType::from_time(delta, self.timings, down, self.vol_settings, &mut self.rng);
 
         self.prev_time.1 = time;
         self.prev_action.1 = Some(typ);
@@ -639,69 +948,2064 @@ impl Replay {
         ))
     }
 
-    fn extended_p1(&mut self, down: bool, frame: u32, x: f32, y: f32, y_accel: f32, rot: f32) {
-        if self.extended_data {
-            self.extended.push(ExtendedAction {
-                player2: false,
-                down,
-                frame,
-                x,
-                y,
-                y_accel,
-                rot,
-                fps_change: None,
-            });
+    fn extended_p1(&mut self, down: bool, frame: u32, x: f32, y: f32, y_accel: f32, rot: f32) {
+        if self.extended_data {
+            self.extended.push(ExtendedAction {
+                player2: false,
+                down,
+                frame,
+                x,
+                y,
+                y_accel,
+                rot,
+                fps_change: None,
+            });
+        }
+    }
+
+    fn extended_p2(&mut self, down: bool, frame: u32, x: f32, y: f32, y_accel: f32, rot: f32) {
+        if self.extended_data {
+            // if x is 0.0, try to get the x position from the first player
+            // FIXME: we probably shouldn't do this for converting replays
+            let x = if x == 0. {
+                if let Some(last) = self.get_last_extended(Player::One) {
+                    last.x
+                } else {
+                    x
+                }
+            } else {
+                x
+            };
+
+            self.extended.push(ExtendedAction {
+                player2: true,
+                down,
+                frame,
+                x,
+                y,
+                y_accel,
+                rot,
+                fps_change: None,
+            });
+        }
+    }
+
+    fn get_last_extended(&self, player: Player) -> Option<ExtendedAction> {
+        // iterate from the back and find the last action for this player
+        for action in self.extended.iter().rev() {
+            if player != Player::Two && action.player2 {
+                continue;
+            }
+            return Some(*action);
+        }
+        None
+    }
+
+    pub fn filter_actions<F>(&self, player: Player, func: F)
+    where
+        F: FnMut(&ExtendedAction),
+    {
+        self.extended
+            .iter()
+            .filter(|a| {
+                (a.player2 && player == Player::Two) || (!a.player2 && player == Player::One)
+            })
+            .for_each(func)
+    }
+
+    /// Remaps all actions with the button index `from` to the button index `to`.
+    ///
+    /// See [`Click::button`] for the button indices. Returns the number of remapped actions.
+    pub fn swap_buttons(&mut self, from: i32, to: i32) -> usize {
+        let mut count = 0;
+        for action in &mut self.actions {
+            if action.click.button() == from {
+                action.click = Click::from_button(to, action.click.click_type());
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Returns a copy of this replay which only contains the actions that are not
+    /// present in `other`.
+    ///
+    /// Actions are considered the same if they have the same player, button and state,
+    /// and are at most 2 frames apart.
+    pub fn subtract(&self, other: &Replay) -> Replay {
+        const FRAME_TOLERANCE: u32 = 2;
+
+        // (player2, button, down) => sorted frames
+        let mut other_frames: HashMap<(bool, i32, bool), Vec<u32>> = HashMap::new();
+        for action in &other.actions {
+            other_frames
+                .entry((
+                    action.player == Player::Two,
+                    action.click.button(),
+                    action.click.is_click(),
+                ))
+                .or_default()
+                .push(action.frame);
+        }
+        other_frames.values_mut().for_each(|frames| frames.sort());
+
+        let mut diff = self.clone();
+        diff.actions.retain(|action| {
+            let Some(frames) = other_frames.get(&(
+                action.player == Player::Two,
+                action.click.button(),
+                action.click.is_click(),
+            )) else {
+                return true;
+            };
+            let start =
+                frames.partition_point(|&f| f < action.frame.saturating_sub(FRAME_TOLERANCE));
+            frames
+                .get(start)
+                .is_none_or(|&f| f > action.frame + FRAME_TOLERANCE)
+        });
+
+        // only keep physics for the remaining actions
+        let remaining: HashSet<(u32, bool)> = diff
+            .actions
+            .iter()
+            .map(|a| (a.frame, a.player == Player::Two))
+            .collect();
+        diff.extended
+            .retain(|e| remaining.contains(&(e.frame, e.player2)));
+        diff.duration = diff.actions.last().map_or(0.0, |a| a.time);
+        diff
+    }
+
+    /// Counts the inputs of each player. Click types are the ones classified with the
+    /// replay [`Timings`] when it was parsed.
+    pub fn input_stats_by_player(&self) -> [PlayerInputStats; 2] {
+        let mut stats = [PlayerInputStats::default(); 2];
+        for action in &self.actions {
+            let s = &mut stats[(action.player == Player::Two) as usize];
+            if action.click.is_click() {
+                s.presses += 1;
+            } else {
+                s.releases += 1;
+            }
+            match action.click.click_type() {
+                ClickType::HardClick => s.hardclicks += 1,
+                ClickType::Click => s.clicks += 1,
+                ClickType::SoftClick => s.softclicks += 1,
+                ClickType::MicroClick => s.microclicks += 1,
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    /// Returns the minimum and maximum player X positions in the replay. Returns `0.0..=0.0`
+    /// if there are no extended actions.
+    pub fn compute_x_range(&self) -> RangeInclusive<f32> {
+        if self.extended.is_empty() {
+            return 0.0..=0.0;
+        }
+        let (min, max) = self
+            .extended
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), e| {
+                (min.min(e.x), max.max(e.x))
+            });
+        min..=max
+    }
+
+    /// Returns a copy of the replay where all X positions are mirrored around the middle of
+    /// [`Replay::compute_x_range`]. Click timings are not changed.
+    pub fn mirror_x_positions(&self) -> Self {
+        let x_range = self.compute_x_range();
+        let midpoint = (x_range.start() + x_range.end()) / 2.0;
+        let mut replay = self.clone();
+        for action in &mut replay.extended {
+            action.x = midpoint + (midpoint - action.x);
+        }
+        replay
+    }
+
+    /// Renders a `width`x`height` heatmap of the clicks in the replay as raw RGBA bytes.
+    /// X is time (from 0 to the replay duration) and Y is the X position of the player
+    /// (higher is further into the level). Cells are colored from blue (few clicks) to
+    /// red (many clicks), empty cells are black.
+    pub fn compute_click_density_image(&self, width: u32, height: u32) -> Vec<u8> {
+        let (w, h) = (width as usize, height as usize);
+        if w == 0 || h == 0 {
+            return vec![];
+        }
+
+        let x_range = self.compute_x_range();
+        let (min_x, range) = (*x_range.start(), x_range.end() - x_range.start());
+        let mut counts = vec![0u32; w * h];
+        for action in self.actions.iter().filter(|a| a.click.is_click()) {
+            let Some(e) = self.extended_at(action.frame, action.player) else {
+                continue;
+            };
+            let col = if self.duration > 0.0 {
+                (action.time / self.duration * w as f64) as usize
+            } else {
+                0
+            };
+            let row = if range > 0.0 {
+                h - 1 - ((e.x - min_x) / range * (h - 1) as f32) as usize
+            } else {
+                h / 2
+            };
+            counts[row.min(h - 1) * w + col.min(w - 1)] += 1;
+        }
+
+        let max_count = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+        // jet colormap: blue -> cyan -> yellow -> red
+        let channel =
+            |t: f32, center: f32| ((1.5 - (4.0 * t - center).abs()).clamp(0.0, 1.0) * 255.0) as u8;
+        counts
+            .iter()
+            .flat_map(|&count| {
+                if count == 0 {
+                    return [0, 0, 0, 255];
+                }
+                let t = count as f32 / max_count;
+                [channel(t, 3.0), channel(t, 2.0), channel(t, 1.0), 255]
+            })
+            .collect()
+    }
+
+    /// Finds the frames where a player restarted the level, by looking for X positions
+    /// that drop by more than [`Replay::RESTART_X_THRESHOLD`] between consecutive
+    /// extended actions of the same player. Useful for formats that don't store deaths.
+    pub fn detect_level_restarts(&self) -> Vec<u32> {
+        self.detect_level_restarts_with_threshold(Self::RESTART_X_THRESHOLD)
+    }
+
+    /// Same as [`Replay::detect_level_restarts`], but with a custom X drop threshold.
+    pub fn detect_level_restarts_with_threshold(&self, threshold: f32) -> Vec<u32> {
+        let mut prev_x = [None::<f32>; 2];
+        let mut restarts = vec![];
+        for e in &self.extended {
+            let prev = &mut prev_x[e.player2 as usize];
+            if prev.is_some_and(|x| x - e.x > threshold) {
+                restarts.push(e.frame);
+            }
+            *prev = Some(e.x);
+        }
+        // both players restart on the same frame
+        restarts.dedup();
+        restarts
+    }
+
+    /// Splits the replay into one replay per attempt, using
+    /// [`Replay::detect_level_restarts`] as the split points. The frames and times of
+    /// every attempt are rebased to start at 0. If the FPS is unknown, the times are
+    /// rebased to the first action of the attempt instead.
+    pub fn split_by_death(&self) -> Vec<Replay> {
+        let restarts = self.detect_level_restarts();
+        let template = Replay {
+            actions: vec![],
+            extended: vec![],
+            events: vec![],
+            ..self.clone()
+        };
+        // (frame, time) of the start of every attempt
+        let restart_times = self.frames_to_times(&restarts);
+        let starts: Vec<(u32, Option<f64>)> = std::iter::once((0, Some(0.0)))
+            .chain(restarts.into_iter().zip(restart_times))
+            .collect();
+        let ends = starts.iter().skip(1).copied().map(Some).chain([None]);
+        starts
+            .iter()
+            .copied()
+            .zip(ends)
+            .map(|((start, start_time), end)| {
+                let in_attempt =
+                    |frame: u32| frame >= start && end.is_none_or(|(end, _)| frame < end);
+                let actions: Vec<&Action> = self
+                    .actions
+                    .iter()
+                    .filter(|a| in_attempt(a.frame))
+                    .collect();
+                let start_time =
+                    start_time.unwrap_or_else(|| actions.first().map_or(0.0, |a| a.time));
+                let end_time = end
+                    .map_or(Some(self.duration), |(_, end_time)| end_time)
+                    .unwrap_or_else(|| actions.last().map_or(start_time, |a| a.time));
+                let mut attempt = template.clone();
+                attempt.duration = (end_time - start_time).max(0.0);
+                attempt.actions = actions
+                    .into_iter()
+                    .map(|a| Action {
+                        frame: a.frame - start,
+                        time: (a.time - start_time).max(0.0),
+                        ..*a
+                    })
+                    .collect();
+                attempt.extended = self
+                    .extended
+                    .iter()
+                    .filter(|e| in_attempt(e.frame))
+                    .map(|e| ExtendedAction {
+                        frame: e.frame - start,
+                        ..*e
+                    })
+                    .collect();
+                attempt.events = self
+                    .events
+                    .iter()
+                    .filter(|e| in_attempt(e.frame))
+                    .map(|e| GameEvent {
+                        frame: e.frame - start,
+                        ..*e
+                    })
+                    .collect();
+                let x_range = attempt.compute_x_range();
+                attempt.x_range = (*x_range.start(), *x_range.end());
+                attempt
+            })
+            .collect()
+    }
+
+    /// Attaches game-side events (orbs, portals, checkpoints...) recorded alongside the
+    /// replay, replacing any previous ones. The events are sorted by frame.
+    pub fn annotate_with_game_events(mut self, mut events: Vec<GameEvent>) -> Self {
+        events.sort_by_key(|e| e.frame);
+        self.events = events;
+        self
+    }
+
+    /// Estimates how far through the level (0-1) the players are at `frame`, by
+    /// interpolating the X position between the nearest extended actions. Falls back to
+    /// `frame / last_frame` if the replay has no X positions.
+    pub fn approximate_level_percentage(&self, frame: u32) -> f64 {
+        let (min_x, max_x) = self.x_range;
+        if max_x > min_x {
+            let idx = self.extended.partition_point(|e| e.frame <= frame);
+            let x = match (
+                idx.checked_sub(1).map(|i| &self.extended[i]),
+                self.extended.get(idx),
+            ) {
+                (Some(a), Some(b)) => {
+                    let t = (frame - a.frame) as f32 / (b.frame - a.frame) as f32;
+                    a.x + (b.x - a.x) * t
+                }
+                (Some(e), None) | (None, Some(e)) => e.x,
+                (None, None) => min_x,
+            };
+            return ((x - min_x) / (max_x - min_x)).clamp(0.0, 1.0) as f64;
+        }
+        let last_frame = self.last_frame();
+        if last_frame == 0 {
+            return 0.0;
+        }
+        frame as f64 / last_frame as f64
+    }
+
+    /// Measures how far the action times are from their frames. Bots click on frame
+    /// boundaries, so jitter means that a parser (or the bot) lost precision. Each action
+    /// is measured with the FPS in effect at its frame.
+    pub fn jitter_analysis(&self) -> JitterStats {
+        const NUM_BUCKETS: usize = 10;
+        let mut stats = JitterStats {
+            jitter_histogram: vec![0; NUM_BUCKETS],
+            ..Default::default()
+        };
+        if self.actions.is_empty() {
+            return stats;
+        }
+        let fps_changes = self.fps_changes();
+        let mut sum = 0.0;
+        for action in &self.actions {
+            let fps = self.fps_at(&fps_changes, action.frame);
+            let jitter = (action.time * fps - action.frame as f64).abs();
+            stats.max_jitter_frames = stats.max_jitter_frames.max(jitter);
+            sum += jitter;
+            let bucket = ((jitter * NUM_BUCKETS as f64) as usize).min(NUM_BUCKETS - 1);
+            stats.jitter_histogram[bucket] += 1;
+        }
+        stats.mean_jitter_frames = sum / self.actions.len() as f64;
+        stats
+    }
+
+    /// Estimates reaction times (in seconds): the delay between a player reaching the apex
+    /// of a jump (`y_accel` crossing 0 downwards) and their next click. The apex is
+    /// interpolated between the extended actions around it. Bots react with a constant
+    /// delay, so a sharply peaked distribution is a sign of a bot. Empty if the replay has
+    /// no physics data.
+    pub fn compute_reaction_time_distribution(&self) -> Vec<f64> {
+        let mut reaction_times = vec![];
+        for player in [Player::One, Player::Two] {
+            let player2 = player == Player::Two;
+            let mut clicks = self
+                .actions
+                .iter()
+                .filter(|a| a.player == player && a.click.is_click())
+                .peekable();
+            let mut prev: Option<&ExtendedAction> = None;
+            for e in self.extended.iter().filter(|e| e.player2 == player2) {
+                if let Some(prev) = prev.filter(|p| p.y_accel > 0.0 && e.y_accel <= 0.0) {
+                    let (t0, t1) = (prev.frame as f64 / self.fps, e.frame as f64 / self.fps);
+                    let apex = t0 + (t1 - t0) * (prev.y_accel / (prev.y_accel - e.y_accel)) as f64;
+                    while clicks.next_if(|a| a.time < apex).is_some() {}
+                    if let Some(click) = clicks.peek() {
+                        reaction_times.push(click.time - apex);
+                    }
+                }
+                prev = Some(e);
+            }
+        }
+        reaction_times
+    }
+
+    /// Returns the times between consecutive actions, in seconds.
+    fn action_deltas(&self) -> Vec<f64> {
+        let mut times: Vec<f64> = self.actions.iter().map(|a| a.time).collect();
+        times.sort_by(f64::total_cmp);
+        times.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
+    /// Converts a time between actions to beats per minute. Returns 0 if the time is 0.
+    fn delta_to_bpm(delta: f64) -> f64 {
+        if delta > 0.0 {
+            60.0 / delta
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the action rate in beats per minute, computed from the mean time between
+    /// actions.
+    pub fn average_action_rate_bpm(&self) -> f64 {
+        let deltas = self.action_deltas();
+        if deltas.is_empty() {
+            return 0.0;
+        }
+        Self::delta_to_bpm(deltas.iter().sum::<f64>() / deltas.len() as f64)
+    }
+
+    /// Returns the action rate in beats per minute, computed from the median time between
+    /// actions. Less sensitive to long pauses than [`Replay::average_action_rate_bpm`].
+    pub fn median_action_rate_bpm(&self) -> f64 {
+        let mut deltas = self.action_deltas();
+        if deltas.is_empty() {
+            return 0.0;
+        }
+        deltas.sort_by(f64::total_cmp);
+        let mid = deltas.len() / 2;
+        let median = if deltas.len() % 2 == 1 {
+            deltas[mid]
+        } else {
+            (deltas[mid - 1] + deltas[mid]) / 2.0
+        };
+        Self::delta_to_bpm(median)
+    }
+
+    /// Returns the Shannon entropy (in bits) of the times between actions, bucketed at 1 ms.
+    /// Bots tend to repeat the same intervals and have lower entropy than human players.
+    pub fn compute_entropy(&self) -> f64 {
+        let deltas = self.action_deltas();
+        let mut histogram: HashMap<u64, usize> = HashMap::new();
+        for delta in &deltas {
+            *histogram
+                .entry((delta * 1000.0).round() as u64)
+                .or_default() += 1;
+        }
+        let total = deltas.len() as f64;
+        histogram
+            .values()
+            .map(|&count| {
+                let p = count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    /// Returns the FPS changes as `(frame, fps)`, sorted by frame. FPS changes are stored
+    /// in the extended action that came before them, so they apply to the frames after it.
+    fn fps_changes(&self) -> Vec<(u32, f64)> {
+        let mut fps_changes: Vec<(u32, f64)> = self
+            .extended
+            .iter()
+            .filter_map(|e| e.fps_change.map(|fps| (e.frame, fps)))
+            .collect();
+        fps_changes.sort_by_key(|&(frame, _)| frame);
+        fps_changes
+    }
+
+    /// Returns the FPS in effect at `frame`, given the result of [`Replay::fps_changes`].
+    fn fps_at(&self, fps_changes: &[(u32, f64)], frame: u32) -> f64 {
+        let num_changes = fps_changes.partition_point(|&(f, _)| f < frame);
+        num_changes
+            .checked_sub(1)
+            .map_or(self.fps, |i| fps_changes[i].1)
+    }
+
+    /// Converts frames to times in seconds with the FPS in effect at each frame, the same
+    /// way the parsers compute action times. `None` where the FPS is unknown (0).
+    pub fn frames_to_times(&self, frames: &[u32]) -> Vec<Option<f64>> {
+        let fps_changes = self.fps_changes();
+        frames
+            .iter()
+            .map(|&frame| {
+                let fps = self.fps_at(&fps_changes, frame);
+                (fps > 0.0).then(|| frame as f64 / fps)
+            })
+            .collect()
+    }
+
+    /// Returns all recorded framerates: the initial one and all FPS changes.
+    fn fps_values(&self) -> impl Iterator<Item = f64> + '_ {
+        std::iter::once(self.fps).chain(self.extended.iter().filter_map(|e| e.fps_change))
+    }
+
+    /// Returns the variance of all recorded framerates (initial + all FPS changes).
+    pub fn fps_variance(&self) -> f64 {
+        let count = self.fps_values().count() as f64;
+        let mean = self.fps_values().sum::<f64>() / count;
+        self.fps_values()
+            .map(|fps| (fps - mean).powi(2))
+            .sum::<f64>()
+            / count
+    }
+
+    /// Returns the lowest and highest recorded framerate.
+    pub fn fps_range(&self) -> (f64, f64) {
+        self.fps_values()
+            .fold((f64::MAX, f64::MIN), |(min, max), fps| {
+                (min.min(fps), max.max(fps))
+            })
+    }
+
+    /// Returns the actions with frames in `start..=end`. Expects the actions to be sorted
+    /// by frame.
+    pub fn actions_within_frame_range_inclusive(&self, start: u32, end: u32) -> Vec<&Action> {
+        if start > end {
+            return vec![];
+        }
+        let from = self.actions.partition_point(|a| a.frame < start);
+        let to = self.actions.partition_point(|a| a.frame <= end);
+        self.actions[from..to].iter().collect()
+    }
+
+    /// Returns whether the first action on `frame` is a click. Returns `false` if there are
+    /// no actions on that frame.
+    fn is_click_at_frame(&self, frame: u32) -> bool {
+        self.actions_within_frame_range_inclusive(frame, frame)
+            .first()
+            .is_some_and(|a| a.click.is_click())
+    }
+
+    /// Finds the physics data for a player at the given frame. Expects the extended
+    /// actions to be sorted by frame.
+    pub fn extended_at(&self, frame: u32, player: Player) -> Option<&ExtendedAction> {
+        let start = self.extended.partition_point(|e| e.frame < frame);
+        self.extended[start..]
+            .iter()
+            .take_while(|e| e.frame == frame)
+            .find(|e| e.player2 == (player == Player::Two))
+    }
+
+    /// Evaluates `expr` for every action and adds the result to its time, so the offset
+    /// is part of the replay itself instead of being applied at render time.
+    pub fn apply_expression_as_time_offset(&mut self, expr: &str, bot: &mut Bot) -> Result<()> {
+        bot.compile_expression(expr)?;
+        let mut prev_frame = 0u32;
+        for i in 0..self.actions.len() {
+            let action = self.actions[i];
+            let extended = self.sample_extended_at(action.time, action.player);
+            bot.update_namespace(&extended, prev_frame, self);
+            prev_frame = extended.frame;
+            let offset = bot.eval_expr()?;
+            self.actions[i].time = (action.time + offset).max(0.0);
+        }
+        self.actions.sort_by(|a, b| a.time.total_cmp(&b.time));
+        self.duration = self.actions.last().map_or(0.0, |a| a.time);
+        Ok(())
+    }
+
+    /// Samples the physics data of a player at the given time, linearly interpolating
+    /// `x`, `y`, `rot` and `y_accel` between the two nearest extended actions. The result
+    /// has the frame at `time` and the `down` state of the previous extended action.
+    /// Expects the extended actions to be sorted by frame.
+    pub fn sample_extended_at(&self, time: f64, player: Player) -> ExtendedAction {
+        let player2 = player == Player::Two;
+        let frame = time * self.fps;
+        let sampled_frame = frame.round().max(0.0) as u32;
+        let idx = self.extended.partition_point(|e| e.frame as f64 <= frame);
+        let prev = self.extended[..idx]
+            .iter()
+            .rev()
+            .find(|e| e.player2 == player2);
+        let next = self.extended[idx..].iter().find(|e| e.player2 == player2);
+        match (prev, next) {
+            (Some(a), Some(b)) => {
+                // `b.frame` is always after `frame`, which is at or after `a.frame`
+                let t = ((frame - a.frame as f64) / (b.frame - a.frame) as f64) as f32;
+                let lerp = |a: f32, b: f32| a + (b - a) * t;
+                ExtendedAction {
+                    x: lerp(a.x, b.x),
+                    y: lerp(a.y, b.y),
+                    rot: lerp(a.rot, b.rot),
+                    y_accel: lerp(a.y_accel, b.y_accel),
+                    frame: sampled_frame,
+                    fps_change: None,
+                    ..*a
+                }
+            }
+            (Some(e), None) | (None, Some(e)) => ExtendedAction {
+                frame: sampled_frame,
+                fps_change: None,
+                ..*e
+            },
+            (None, None) => ExtendedAction {
+                player2,
+                frame: sampled_frame,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns a binary matrix for machine learning, one row per `bucket_frames` frames.
+    /// The columns are [`Replay::SPARSE_MATRIX_COLUMNS`]: `down`/`up` are presses and
+    /// releases of the regular button, `left`/`right` are presses of the platformer
+    /// buttons. A cell is `true` if the event happens in the bucket.
+    pub fn to_sparse_matrix(&self, bucket_frames: u32) -> Vec<Vec<bool>> {
+        let bucket_frames = bucket_frames.max(1);
+        let Some(last_frame) = self.actions.iter().map(|a| a.frame).max() else {
+            return vec![];
+        };
+        let mut matrix = vec![vec![false; 8]; (last_frame / bucket_frames) as usize + 1];
+        for action in &self.actions {
+            let column = match action.click {
+                Click::Regular(_) if action.click.is_click() => 0,
+                Click::Regular(_) if action.click.is_release() => 1,
+                Click::Left(_) if action.click.is_click() => 4,
+                Click::Right(_) if action.click.is_click() => 5,
+                _ => continue,
+            };
+            let column = match action.player {
+                Player::One => column,
+                Player::Two => column + 2,
+            };
+            matrix[(action.frame / bucket_frames) as usize][column] = true;
+        }
+        matrix
+    }
+
+    /// Builds a directed graph where each node is an action and each edge goes to the
+    /// next action of the same player, weighted by the time between them (in seconds).
+    #[cfg(feature = "graph")]
+    pub fn to_action_graph(&self) -> petgraph::Graph<Action, f64> {
+        let mut graph = petgraph::Graph::new();
+        let mut prev: [Option<(petgraph::graph::NodeIndex, f64)>; 2] = [None; 2];
+        for action in &self.actions {
+            let node = graph.add_node(*action);
+            let prev = &mut prev[(action.player == Player::Two) as usize];
+            if let Some((prev_node, prev_time)) = *prev {
+                graph.add_edge(prev_node, node, action.time - prev_time);
+            }
+            *prev = Some((node, action.time));
+        }
+        graph
+    }
+
+    /// Serializes the replay as GDR, compresses it with zlib and encodes it as base64, so
+    /// it can be shared as text. See [`Replay::import_from_base64_string`].
+    pub fn to_base64_string(&self, format: gdr::GdrFormat) -> Result<String> {
+        use flate2::{write::ZlibEncoder, Compression};
+        let gdr = self.to_gdr();
+        let data = match format {
+            gdr::GdrFormat::Json => gdr.to_json_bytes()?,
+            gdr::GdrFormat::MsgPack => gdr.to_msgpack_bytes()?,
+        };
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&data)?;
+        Ok(base64_encode(&encoder.finish()?))
+    }
+
+    /// Parses a replay encoded with [`Replay::to_base64_string`]. Whitespace is ignored.
+    pub fn import_from_base64_string(self, encoded: &str) -> Result<Self> {
+        use flate2::read::ZlibDecoder;
+        let compressed = base64_decode(encoded)?;
+        let mut data = vec![];
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut data)
+            .context("invalid compressed replay data")?;
+        self.parse_bytes(ReplayType::Gdr, &data)
+    }
+
+    /// Writes the replay as GDR JSON with a `$schema` field pointing to
+    /// [`Replay::GDR_SCHEMA_URL`]. The output is validated against the schema before
+    /// anything is written.
+    #[cfg(feature = "json-schema")]
+    pub fn export_as_json_schema_validated<W: Write>(&self, writer: W) -> Result<()> {
+        let schema: serde_json::Value =
+            serde_json::from_str(GDR_SCHEMA).expect("embedded GDR schema is valid JSON");
+        let mut value = serde_json::to_value(self.to_gdr())?;
+        value
+            .as_object_mut()
+            .context("GDR replay is not a JSON object")?
+            .insert("$schema".to_string(), Self::GDR_SCHEMA_URL.into());
+
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| anyhow::anyhow!("invalid GDR schema: {e}"))?;
+        if let Some(e) = validator.iter_errors(&value).next() {
+            anyhow::bail!(
+                "exported replay doesn't match the GDR schema: {e} (at {})",
+                e.instance_path
+            );
+        }
+        serde_json::to_writer(writer, &value)?;
+        Ok(())
+    }
+
+    /// Converts the replay to a [GDReplayFormat](gdr) replay.
+    pub fn to_gdr(&self) -> gdr::Replay {
+        let mut replay = gdr::Replay {
+            duration: self.duration as f32,
+            framerate: self.fps as f32,
+            bot: gdr::BotInfo::new("zcb3", ""),
+            ..Default::default()
+        };
+        for action in &self.actions {
+            let player2 = action.player == Player::Two;
+            let mut input = gdr::Input::new(
+                action.frame,
+                action.click.button(),
+                player2,
+                action.click.is_click(),
+            );
+            input.correction.time = action.time as f32;
+            input.correction.player2 = player2;
+            if let Some(e) = self.extended_at(action.frame, action.player) {
+                input.correction.x_pos = e.x;
+                input.correction.y_pos = e.y;
+                input.correction.y_vel = e.y_accel;
+                input.correction.rotation = e.rot;
+            }
+            replay.inputs.push(input);
+        }
+        replay
+    }
+
+    /// Removes all releases, returning the number of removed actions.
+    pub fn remove_all_releases(&mut self) -> usize {
+        self.remove_actions_where_down(false)
+    }
+
+    /// Removes all clicks (presses), returning the number of removed actions.
+    pub fn remove_all_clicks(&mut self) -> usize {
+        self.remove_actions_where_down(true)
+    }
+
+    /// Removes all actions that are presses (`down == true`) or releases. Physics entries
+    /// that only belonged to removed actions are removed too.
+    fn remove_actions_where_down(&mut self, down: bool) -> usize {
+        let num_actions = self.actions.len();
+        self.actions.retain(|a| a.click.is_click() != down);
+        let remaining: HashSet<(u32, bool)> = self
+            .actions
+            .iter()
+            .map(|a| (a.frame, a.player == Player::Two))
+            .collect();
+        self.extended
+            .retain(|e| e.down != down || remaining.contains(&(e.frame, e.player2)));
+        num_actions - self.actions.len()
+    }
+
+    /// Groups actions that happen within `tolerance_frames` of the previous action, for
+    /// example when both players click at the same time. Only groups with at least two
+    /// actions are returned. Expects the actions to be sorted.
+    pub fn group_simultaneous_actions(&self, tolerance_frames: u32) -> Vec<Vec<Action>> {
+        let mut groups = vec![];
+        let mut group: Vec<Action> = vec![];
+        for action in &self.actions {
+            if group
+                .last()
+                .is_some_and(|prev| action.frame.saturating_sub(prev.frame) > tolerance_frames)
+            {
+                if group.len() >= 2 {
+                    groups.push(std::mem::take(&mut group));
+                } else {
+                    group.clear();
+                }
+            }
+            group.push(*action);
+        }
+        if group.len() >= 2 {
+            groups.push(group);
+        }
+        groups
+    }
+
+    /// Returns a copy of the replay that only keeps every `every_n`-th action, along with
+    /// the physics entries of the kept actions. Useful for quick previews.
+    pub fn subsample(&self, every_n: usize) -> Self {
+        let every_n = every_n.max(1);
+        let mut replay = self.clone();
+        replay.actions = self.actions.iter().step_by(every_n).copied().collect();
+        replay.actions.sort_by(|a, b| a.time.total_cmp(&b.time));
+        let kept: HashSet<(u32, bool)> = replay
+            .actions
+            .iter()
+            .map(|a| (a.frame, a.player == Player::Two))
+            .collect();
+        replay
+            .extended
+            .retain(|e| kept.contains(&(e.frame, e.player2)));
+        replay.extended.sort_by_key(|e| e.frame);
+        replay
+    }
+
+    /// Removes actions that repeat the previous state of the same player and button, e.g.
+    /// a press right after another press. Only the first action of each run is kept.
+    /// Returns the number of removed actions.
+    pub fn deduplicate_consecutive_same_state(&mut self) -> usize {
+        let num_actions = self.actions.len();
+        let mut states: HashMap<(bool, i32), bool> = HashMap::new();
+        let mut removed: HashSet<(u32, bool)> = HashSet::new();
+        self.actions.retain(|a| {
+            let key = (a.player == Player::Two, a.click.button());
+            let down = a.click.is_click();
+            let keep = states.insert(key, down) != Some(down);
+            if !keep {
+                removed.insert((a.frame, key.0));
+            }
+            keep
+        });
+
+        // drop the physics data of removed actions, unless another action uses the same frame
+        let remaining: HashSet<(u32, bool)> = self
+            .actions
+            .iter()
+            .map(|a| (a.frame, a.player == Player::Two))
+            .collect();
+        self.extended.retain(|e| {
+            let key = (e.frame, e.player2);
+            !removed.contains(&key) || remaining.contains(&key)
+        });
+        num_actions - self.actions.len()
+    }
+
+    /// Returns a copy of the replay where every action time `t` is replaced by `warp_fn(t)`.
+    /// Frames are recomputed from the new times, the order of the actions is kept.
+    pub fn time_warp(&self, warp_fn: impl Fn(f64) -> f64) -> Self {
+        let mut replay = self.clone();
+        for action in &mut replay.actions {
+            action.time = warp_fn(action.time);
+            action.frame = (action.time * self.fps).round() as u32;
+        }
+        for action in &mut replay.extended {
+            action.frame = (warp_fn(action.frame as f64 / self.fps) * self.fps).round() as u32;
+        }
+        if let Some(last) = replay.actions.last() {
+            replay.duration = last.time;
+        }
+        replay
+    }
+
+    /// Warps time with `t.powf(factor)`, scaled so the replay keeps its duration. With
+    /// factors above 1, actions at the start are closer together (faster) and actions at the
+    /// end are further apart. Factors below 1 do the opposite.
+    pub fn exponential_speedup(&self, factor: f32) -> Self {
+        let (duration, factor) = (self.duration, factor as f64);
+        if duration <= 0.0 {
+            return self.clone();
+        }
+        self.time_warp(|t| (t / duration).max(0.0).powf(factor) * duration)
+    }
+
+    /// Returns a copy of the replay where all presses are releases and vice versa.
+    pub fn invert_all_inputs(&self) -> Self {
+        let mut replay = self.clone();
+        for action in &mut replay.actions {
+            action.click =
+                Click::from_button(action.click.button(), action.click.click_type().inverted());
+        }
+        for action in &mut replay.extended {
+            action.down = !action.down;
+        }
+        replay
+    }
+
+    /// Formats the actions as a table, one row per action. Only the first 1000 actions
+    /// are included.
+    pub fn to_human_readable_string(&self) -> String {
+        use std::fmt::Write;
+        const MAX_ACTIONS: usize = 1000;
+
+        let mut s = String::from("Frame | Time (s) | Player | Button | Down\n");
+        for action in self.actions.iter().take(MAX_ACTIONS) {
+            let _ = writeln!(
+                s,
+                "{:>5} | {:>8.3} | {:>6} | {:>6} | {}",
+                action.frame,
+                action.time,
+                if action.player == Player::One { 1 } else { 2 },
+                action.click.button(),
+                action.click.is_click(),
+            );
+        }
+        if self.actions.len() > MAX_ACTIONS {
+            let _ = writeln!(
+                s,
+                "(showing {MAX_ACTIONS} of {} actions)",
+                self.actions.len()
+            );
+        }
+        s
+    }
+
+    /// Exports the replay as an [FCEUX movie](https://fceux.com/web/FM2.html).
+    ///
+    /// Clicks of the first and second player are mapped to the A button of the
+    /// controllers in port 0 and port 1.
+    pub fn export_as_fceux_fm2<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "version 3")?;
+        writeln!(writer, "emuVersion 22020")?;
+        writeln!(writer, "rerecordCount 0")?;
+        writeln!(writer, "palFlag 0")?;
+        writeln!(writer, "romFilename zcb")?;
+        writeln!(writer, "romChecksum base64:AAAAAAAAAAAAAAAAAAAAAA==")?;
+        writeln!(writer, "guid 00000000-0000-0000-0000-000000000000")?;
+        writeln!(writer, "fourscore 0")?;
+        writeln!(writer, "microphone 0")?;
+        writeln!(writer, "port0 1")?;
+        writeln!(writer, "port1 1")?;
+        writeln!(writer, "port2 0")?;
+        writeln!(writer, "FDS 0")?;
+        writeln!(writer, "NewPPU 0")?;
+
+        let mut actions: Vec<&Action> = self.actions.iter().collect();
+        actions.sort_by_key(|a| a.frame);
+        let last_frame = actions.last().map_or(0, |a| a.frame);
+
+        // buttons are "RLDUTSBA", A is the last one
+        let pad = |pressed: bool| if pressed { ".......A" } else { "........" };
+        let mut down = [false; 2];
+        let mut actions = actions.into_iter().peekable();
+        for frame in 0..=last_frame {
+            // make sure clicks that are released on the same frame are still visible
+            let mut pressed = [false; 2];
+            while let Some(action) = actions.next_if(|a| a.frame == frame) {
+                let idx = (action.player == Player::Two) as usize;
+                down[idx] = action.click.is_click();
+                pressed[idx] |= down[idx];
+            }
+            writeln!(
+                writer,
+                "|0|{}|{}||",
+                pad(down[0] || pressed[0]),
+                pad(down[1] || pressed[1])
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the replay as an osu! beatmap (`.osu`). Every click becomes a hit circle
+    /// placed horizontally by the player X position, so the beatmap can be used for rhythm
+    /// analysis and storyboarding.
+    pub fn export_hitobjects_osu<W: Write>(&self, mut writer: W, circle_size: f32) -> Result<()> {
+        const PLAYFIELD_WIDTH: f32 = 512.0;
+        const PLAYFIELD_HEIGHT: f32 = 384.0;
+
+        writeln!(writer, "osu file format v14")?;
+        writeln!(writer)?;
+        writeln!(writer, "[General]")?;
+        writeln!(writer, "AudioFilename: audio.wav")?;
+        writeln!(writer, "AudioLeadIn: 0")?;
+        writeln!(writer, "Mode: 0")?;
+        writeln!(writer)?;
+        writeln!(writer, "[Metadata]")?;
+        writeln!(writer, "Title:zcb3 replay")?;
+        writeln!(writer, "Artist:zcb3")?;
+        writeln!(writer, "Creator:zcb3")?;
+        writeln!(writer, "Version:Clicks")?;
+        writeln!(writer)?;
+        writeln!(writer, "[Difficulty]")?;
+        writeln!(writer, "HPDrainRate:5")?;
+        writeln!(writer, "CircleSize:{circle_size}")?;
+        writeln!(writer, "OverallDifficulty:5")?;
+        writeln!(writer, "ApproachRate:5")?;
+        writeln!(writer, "SliderMultiplier:1.4")?;
+        writeln!(writer, "SliderTickRate:1")?;
+        writeln!(writer)?;
+        writeln!(writer, "[TimingPoints]")?;
+        writeln!(writer, "0,500,4,2,0,100,1,0")?;
+        writeln!(writer)?;
+        writeln!(writer, "[HitObjects]")?;
+
+        let x_range = self.compute_x_range();
+        let (min_x, range) = (*x_range.start(), x_range.end() - x_range.start());
+        for (i, action) in self
+            .actions
+            .iter()
+            .filter(|a| a.click.is_click())
+            .enumerate()
+        {
+            let x = self
+                .extended_at(action.frame, action.player)
+                .filter(|_| range > 0.0)
+                .map_or(PLAYFIELD_WIDTH / 2.0, |e| {
+                    (e.x - min_x) / range * PLAYFIELD_WIDTH
+                });
+            let time = (action.time * 1000.0).round() as i64;
+            // type 1 is a hit circle, 4 starts a new combo
+            let typ = if i == 0 { 5 } else { 1 };
+            writeln!(
+                writer,
+                "{},{},{time},{typ},0,0:0:0:0:",
+                x.round() as i32,
+                (PLAYFIELD_HEIGHT / 2.0) as i32
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the replay as a StepMania chart (`.sm`) with a constant `bpm`. Every action
+    /// is a tap note at beat `time * bpm / 60`. Player 1 clicks and releases are on columns
+    /// 0 and 1, player 2 on columns 2 and 3.
+    pub fn export_as_stepmania_sm<W: Write>(&self, mut writer: W, bpm: f64) -> Result<()> {
+        /// Finest note resolution StepMania supports, in rows per 4-beat measure.
+        const TICKS_PER_MEASURE: u64 = 192;
+        /// Allowed rows per measure, the coarsest one that fits all notes is used.
+        const ROWS_PER_MEASURE: [u64; 9] = [4, 8, 12, 16, 24, 32, 48, 64, 192];
+
+        anyhow::ensure!(bpm > 0.0, "BPM must be positive");
+
+        // (tick, column) of every note
+        let mut notes: Vec<(u64, usize)> = self
+            .actions
+            .iter()
+            .map(|a| {
+                let beat = a.time * bpm / 60.0;
+                let tick = (beat / 4.0 * TICKS_PER_MEASURE as f64).round() as u64;
+                let column = (a.player == Player::Two) as usize * 2 + a.click.is_release() as usize;
+                (tick, column)
+            })
+            .collect();
+        notes.sort_unstable();
+        let num_measures = notes.last().map_or(1, |n| n.0 / TICKS_PER_MEASURE + 1);
+
+        writeln!(writer, "#TITLE:zcb3 replay;")?;
+        writeln!(writer, "#ARTIST:zcb3;")?;
+        writeln!(writer, "#MUSIC:audio.wav;")?;
+        writeln!(writer, "#OFFSET:0.000000;")?;
+        writeln!(writer, "#BPMS:0.000000={bpm:.6};")?;
+        writeln!(writer)?;
+        writeln!(writer, "#NOTES:")?;
+        writeln!(writer, "     dance-single:")?;
+        writeln!(writer, "     zcb3:")?;
+        writeln!(writer, "     Edit:")?;
+        writeln!(writer, "     1:")?;
+        writeln!(writer, "     0,0,0,0,0:")?;
+
+        let mut notes = notes.as_slice();
+        for measure in 0..num_measures {
+            let count = notes.partition_point(|n| n.0 / TICKS_PER_MEASURE == measure);
+            let (in_measure, rest) = notes.split_at(count);
+            notes = rest;
+
+            let rows = ROWS_PER_MEASURE
+                .into_iter()
+                .find(|&rows| {
+                    let ticks_per_row = TICKS_PER_MEASURE / rows;
+                    in_measure.iter().all(|n| n.0 % ticks_per_row == 0)
+                })
+                .unwrap_or(TICKS_PER_MEASURE);
+            let ticks_per_row = TICKS_PER_MEASURE / rows;
+            let mut grid = vec![*b"0000"; rows as usize];
+            for &(tick, column) in in_measure {
+                grid[((tick % TICKS_PER_MEASURE) / ticks_per_row) as usize][column] = b'1';
+            }
+            for row in &grid {
+                writeln!(writer, "{}", std::str::from_utf8(row)?)?;
+            }
+            writeln!(
+                writer,
+                "{}",
+                if measure + 1 == num_measures {
+                    ";"
+                } else {
+                    ","
+                }
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the replay as a generic XML document:
+    /// `<Replay><Inputs><Input frame="..." down="..." player2="..." button="..."/></Inputs></Replay>`.
+    pub fn export_xml<W: Write>(&self, mut writer: W) -> Result<()> {
+        // replays don't store their author, and all values are numbers or booleans, so
+        // nothing has to be escaped
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<Replay fps="{}" author="" duration="{}">"#,
+            self.fps, self.duration
+        )?;
+        writeln!(writer, "  <Inputs>")?;
+        for action in &self.actions {
+            writeln!(
+                writer,
+                r#"    <Input frame="{}" down="{}" player2="{}" button="{}"/>"#,
+                action.frame,
+                action.click.is_click(),
+                action.player == Player::Two,
+                action.click.button()
+            )?;
+        }
+        writeln!(writer, "  </Inputs>")?;
+        writeln!(writer, "</Replay>")?;
+        Ok(())
+    }
+
+    /// Writes a TouchOSC layout (the `index.xml` of a `.touchosc` file) where every click
+    /// is a momentary push button. Buttons are placed horizontally by time, player 1 on the
+    /// top half and player 2 on the bottom half of the page.
+    pub fn export_as_touchosc<W: Write>(&self, mut writer: W) -> Result<()> {
+        const LAYOUT_SIZE: (f64, f64) = (1024.0, 768.0);
+        const BUTTON_SIZE: f64 = 16.0;
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<layout version="17" mode="3" orientation="vertical" w="{}" h="{}">"#,
+            LAYOUT_SIZE.0, LAYOUT_SIZE.1
+        )?;
+        writeln!(
+            writer,
+            r#"  <tabpage name="{}" scalef="0.0" scalet="1.0">"#,
+            base64_encode(b"replay")
+        )?;
+        let clicks = self.actions.iter().filter(|a| a.click.is_click());
+        for (i, action) in clicks.enumerate() {
+            let x = if self.duration > 0.0 {
+                action.time / self.duration * (LAYOUT_SIZE.0 - BUTTON_SIZE)
+            } else {
+                0.0
+            };
+            let (player, y, color) = match action.player {
+                Player::One => (1, LAYOUT_SIZE.1 / 4.0, "blue"),
+                Player::Two => (2, LAYOUT_SIZE.1 * 3.0 / 4.0, "red"),
+            };
+            // names and OSC addresses are base64 encoded in TouchOSC layouts
+            writeln!(
+                writer,
+                r#"    <control name="{}" x="{}" y="{}" w="{BUTTON_SIZE}" h="{BUTTON_SIZE}" color="{color}" scalef="0.0" scalet="1.0" osc_cs="{}" type="push" local_off="false" sp="true" sr="true"></control>"#,
+                base64_encode(format!("push{}", i + 1).as_bytes()),
+                x.round(),
+                y.round(),
+                base64_encode(format!("/zcb/p{player}/{}", action.frame).as_bytes()),
+            )?;
+        }
+        writeln!(writer, "  </tabpage>")?;
+        writeln!(writer, "</layout>")?;
+        Ok(())
+    }
+
+    /// Writes an ASS subtitle file with one 100 ms caption per action. Player 1 captions
+    /// are blue and player 2 captions are red, positioned at the bottom of a 1080p frame
+    /// according to the X position of the player.
+    pub fn export_as_subtitle_ass<W: Write>(&self, mut writer: W) -> Result<()> {
+        const CAPTION_DURATION: f64 = 0.1;
+        const PLAY_RES: (f32, f32) = (1920.0, 1080.0);
+        const MARGIN: f32 = 100.0;
+        // ASS timestamps are `H:MM:SS.cc`
+        let timestamp = |secs: f64| {
+            let cs = (secs * 100.0).round() as u64;
+            format!(
+                "{}:{:02}:{:02}.{:02}",
+                cs / 360_000,
+                cs / 6000 % 60,
+                cs / 100 % 60,
+                cs % 100
+            )
+        };
+
+        writeln!(writer, "[Script Info]")?;
+        writeln!(writer, "ScriptType: v4.00+")?;
+        writeln!(writer, "PlayResX: {}", PLAY_RES.0)?;
+        writeln!(writer, "PlayResY: {}", PLAY_RES.1)?;
+        writeln!(writer)?;
+        writeln!(writer, "[V4+ Styles]")?;
+        writeln!(
+            writer,
+            "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+            BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+            BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding"
+        )?;
+        writeln!(
+            writer,
+            "Style: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,\
+            100,100,0,0,1,2,0,2,10,10,40,1"
+        )?;
+        writeln!(writer)?;
+        writeln!(writer, "[Events]")?;
+        writeln!(
+            writer,
+            "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text"
+        )?;
+
+        let x_range = self.compute_x_range();
+        let (min_x, range) = (*x_range.start(), x_range.end() - x_range.start());
+        for action in &self.actions {
+            let x = self
+                .extended_at(action.frame, action.player)
+                .filter(|_| range > 0.0)
+                .map_or(PLAY_RES.0 / 2.0, |e| {
+                    MARGIN + (e.x - min_x) / range * (PLAY_RES.0 - MARGIN * 2.0)
+                });
+            // colours are in BGR order
+            let (colour, player) = match action.player {
+                Player::One => ("&HFF0000&", 1),
+                Player::Two => ("&H0000FF&", 2),
+            };
+            writeln!(
+                writer,
+                "Dialogue: 0,{},{},Default,,0,0,0,,{{\\an2\\pos({},{})\\c{colour}}}P{player} {:?}",
+                timestamp(action.time),
+                timestamp(action.time + CAPTION_DURATION),
+                x.round() as i32,
+                (PLAY_RES.1 - MARGIN / 2.0) as i32,
+                action.click.click_type()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Finds dense spam regions: runs of at least `min_clicks` clicks where each click
+    /// follows the previous one within the spam time of the volume settings. Returns the
+    /// time range of each region (in seconds).
+    pub fn spam_regions(&self, min_clicks: usize) -> Vec<RangeInclusive<f64>> {
+        let max_interval = self.vol_settings.spam_time;
+        let mut regions = vec![];
+        let mut clicks = self.actions.iter().filter(|a| a.click.is_click());
+        let Some(first) = clicks.next() else {
+            return regions;
+        };
+        let (mut start, mut prev, mut count) = (first.time, first.time, 1);
+        for action in clicks {
+            if action.time - prev > max_interval {
+                if count >= min_clicks {
+                    regions.push(start..=prev);
+                }
+                (start, count) = (action.time, 0);
+            }
+            prev = action.time;
+            count += 1;
+        }
+        if count >= min_clicks {
+            regions.push(start..=prev);
+        }
+        regions
+    }
+
+    /// Writes OBS-style chapter markers (`HH:MM:SS Title`), one per death (see
+    /// [`Replay::detect_level_restarts`]) and one per spam region found by
+    /// [`Replay::spam_regions`]. The first chapter always starts at `00:00:00`.
+    pub fn export_as_obs_chapter_markers<W: Write>(&self, mut writer: W) -> Result<()> {
+        const MIN_SPAM_CLICKS: usize = 8;
+        let timestamp = |secs: f64| {
+            let secs = secs as u64;
+            format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        };
+        let mut chapters = vec![];
+        let deaths = self.frames_to_times(&self.detect_level_restarts());
+        for (i, time) in deaths.into_iter().enumerate() {
+            if let Some(time) = time {
+                chapters.push((time, format!("Death {}", i + 1)));
+            }
+        }
+        for (i, region) in self.spam_regions(MIN_SPAM_CLICKS).iter().enumerate() {
+            chapters.push((*region.start(), format!("Spam {}", i + 1)));
+        }
+        chapters.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        writeln!(writer, "{} Start", timestamp(0.0))?;
+        let mut prev = 0.0;
+        for (time, name) in chapters {
+            // chapters can't share the same second
+            if time.floor() <= prev {
+                continue;
+            }
+            prev = time.floor();
+            writeln!(writer, "{} {name}", timestamp(time))?;
+        }
+        Ok(())
+    }
+
+    /// Writes Vorbis comment chapter tags (`CHAPTER001=HH:MM:SS.mmm`, `CHAPTER001NAME=...`)
+    /// for the start of the replay, every death (see [`Replay::detect_level_restarts`]),
+    /// every FPS change and the start and end of every spam region. The output can be
+    /// added to a rendered OGG file with `vorbiscomment -a -c`.
+    pub fn export_as_ogg_chapters<W: Write>(&self, mut writer: W) -> Result<()> {
+        const MIN_SPAM_CLICKS: usize = 8;
+        let mut chapters = vec![(0.0, "Start".to_string())];
+        let deaths = self.frames_to_times(&self.detect_level_restarts());
+        for (i, time) in deaths.into_iter().enumerate() {
+            if let Some(time) = time {
+                chapters.push((time, format!("Death {}", i + 1)));
+            }
+        }
+        let fps_changes = self.fps_changes();
+        let frames: Vec<u32> = fps_changes.iter().map(|&(frame, _)| frame).collect();
+        for ((_, fps), time) in fps_changes.iter().zip(self.frames_to_times(&frames)) {
+            if let Some(time) = time {
+                chapters.push((time, format!("FPS change to {fps}")));
+            }
+        }
+        for (i, region) in self.spam_regions(MIN_SPAM_CLICKS).iter().enumerate() {
+            chapters.push((*region.start(), format!("Spam {} start", i + 1)));
+            chapters.push((*region.end(), format!("Spam {} end", i + 1)));
+        }
+        chapters.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (i, (time, name)) in chapters.iter().enumerate() {
+            let ms = (time.max(0.0) * 1000.0).round() as u64;
+            writeln!(
+                writer,
+                "CHAPTER{:03}={:02}:{:02}:{:02}.{:03}",
+                i + 1,
+                ms / 3_600_000,
+                ms / 60_000 % 60,
+                ms / 1000 % 60,
+                ms % 1000
+            )?;
+            writeln!(writer, "CHAPTER{:03}NAME={name}", i + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a single-track (SMF type 0) MIDI file with one short note per action. The
+    /// PPQN is `fps` rounded to a whole number and one tick is `1 / PPQN` seconds. The
+    /// velocity depends on the click type (hard = 127, micro = 40), player 1 uses MIDI
+    /// channel 1 and player 2 uses channel 2.
+    pub fn export_smf0_midi<W: Write>(&self, mut writer: W, fps: f64, key: u8) -> Result<()> {
+        if !(1.0..=32767.0).contains(&fps) {
+            anyhow::bail!("MIDI format only supports 1 to 32767 PPQN (framerate), got {fps}");
+        }
+        let key = key.min(127);
+        let ppqn = fps.round() as u16;
+
+        // (tick, status, velocity), note-offs are sorted before note-ons on the same tick
+        let mut events: Vec<(u32, u8, u8)> = Vec::with_capacity(self.actions.len() * 2);
+        for action in &self.actions {
+            let tick = (action.time * ppqn as f64).round().max(0.0) as u32;
+            let channel = if action.player == Player::One { 0 } else { 1 };
+            let velocity = match action.click.click_type() {
+                ClickType::HardClick | ClickType::HardRelease => 127,
+                ClickType::Click | ClickType::Release => 100,
+                ClickType::SoftClick | ClickType::SoftRelease => 70,
+                _ => 40,
+            };
+            events.push((tick, 0x90 | channel, velocity));
+            events.push((tick + 1, 0x80 | channel, 0));
+        }
+        events.sort_by_key(|&(tick, status, _)| (tick, status & 0xF0 == 0x90));
+
+        let mut track = vec![];
+        track.extend([0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40]); // 60 bpm
+        let mut prev_tick = 0;
+        for (tick, status, velocity) in events {
+            write_vlq(&mut track, tick - prev_tick);
+            track.extend([status, key, velocity]);
+            prev_tick = tick;
+        }
+        track.extend([0x00, 0xFF, 0x2F, 0x00]); // end of track
+
+        // all values are big endian
+        writer.write_all(b"MThd")?;
+        writer.write_all(&6u32.to_be_bytes())?;
+        writer.write_all(&0u16.to_be_bytes())?; // SMF format
+        writer.write_all(&1u16.to_be_bytes())?; // number of tracks
+        writer.write_all(&ppqn.to_be_bytes())?; // PPQN
+        writer.write_all(b"MTrk")?;
+        writer.write_all(&(track.len() as u32).to_be_bytes())?;
+        writer.write_all(&track)?;
+        Ok(())
+    }
+
+    /// Writes a SMIL 3.0 presentation with one `<audio>` element per action. Every element
+    /// plays the part of the rendered `clicks.wav` (next to the .smil file) from its action
+    /// until the next one, so the clips line up with a video played alongside.
+    pub fn export_as_smil<W: Write>(&self, mut writer: W) -> Result<()> {
+        const AUDIO_SRC: &str = "clicks.wav";
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<smil xmlns="http://www.w3.org/ns/SMIL" version="3.0" baseProfile="Language">"#
+        )?;
+        writeln!(writer, "  <head>")?;
+        writeln!(writer, r#"    <meta name="title" content="ZCB3 replay"/>"#)?;
+        writeln!(writer, "  </head>")?;
+        writeln!(writer, "  <body>")?;
+        writeln!(writer, r#"    <par dur="{:.3}s">"#, self.duration)?;
+        for (i, action) in self.actions.iter().enumerate() {
+            // simultaneous actions share the clip until the next distinct time
+            let clip_end = self.actions[i + 1..]
+                .iter()
+                .map(|a| a.time)
+                .find(|&t| t > action.time)
+                .unwrap_or(self.duration)
+                .max(action.time);
+            writeln!(
+                writer,
+                r#"      <audio src="{AUDIO_SRC}" begin="{:.3}s" clipBegin="{:.3}s" clipEnd="{:.3}s" title="{:?} (player {})"/>"#,
+                action.time,
+                action.time,
+                clip_end,
+                action.click.click_type(),
+                if action.player == Player::One { 1 } else { 2 }
+            )?;
+        }
+        writeln!(writer, "    </par>")?;
+        writeln!(writer, "  </body>")?;
+        writeln!(writer, "</smil>")?;
+        Ok(())
+    }
+
+    /// Writes a minimal Final Cut Pro X (FCPXML 1.9) project with one marker per action,
+    /// titled by the click type. Markers are snapped to a 60 FPS timeline.
+    pub fn export_as_fcpxml<W: Write>(&self, mut writer: W) -> Result<()> {
+        const TIMELINE_FPS: f64 = 60.0;
+        // FCPXML times are rational numbers of seconds
+        let timecode = |secs: f64| format!("{}/60s", (secs * TIMELINE_FPS).round() as u64);
+        let duration = timecode(self.duration + 1.0);
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, "<!DOCTYPE fcpxml>")?;
+        writeln!(writer, r#"<fcpxml version="1.9">"#)?;
+        writeln!(writer, "  <resources>")?;
+        writeln!(
+            writer,
+            r#"    <format id="r1" name="FFVideoFormat1080p60" frameDuration="1/60s" width="1920" height="1080"/>"#
+        )?;
+        writeln!(writer, "  </resources>")?;
+        writeln!(writer, "  <library>")?;
+        writeln!(writer, r#"    <event name="zcb3">"#)?;
+        writeln!(writer, r#"      <project name="Replay">"#)?;
+        writeln!(
+            writer,
+            r#"        <sequence format="r1" duration="{duration}" tcStart="0s" tcFormat="NDF">"#
+        )?;
+        writeln!(writer, "          <spine>")?;
+        writeln!(
+            writer,
+            r#"            <gap name="Gap" offset="0s" duration="{duration}" start="0s">"#
+        )?;
+        for action in &self.actions {
+            writeln!(
+                writer,
+                r#"              <marker start="{}" duration="1/60s" value="{:?}"/>"#,
+                timecode(action.time),
+                action.click.click_type()
+            )?;
+        }
+        writeln!(writer, "            </gap>")?;
+        writeln!(writer, "          </spine>")?;
+        writeln!(writer, "        </sequence>")?;
+        writeln!(writer, "      </project>")?;
+        writeln!(writer, "    </event>")?;
+        writeln!(writer, "  </library>")?;
+        writeln!(writer, "</fcpxml>")?;
+        Ok(())
+    }
+
+    /// Writes a piano roll of the replay as a `width`x`height` SVG. Every click type is a
+    /// note (hard click = C5, click = A4, soft click = G4, micro click = E4, releases an
+    /// octave lower). Presses are drawn as bars until the release, releases as short bars.
+    /// Player 1 is blue and player 2 is orange.
+    pub fn export_as_piano_roll_svg<W: Write>(
+        &self,
+        mut writer: W,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        /// Width of the Y axis labels.
+        const LABEL_WIDTH: f64 = 110.0;
+        /// Length of release notes, in seconds.
+        const RELEASE_LENGTH: f64 = 0.05;
+        const PLAYER_COLORS: [&str; 2] = ["#4080ff", "#ff8040"];
+        /// Rows from top to bottom: click type and MIDI note name.
+        const ROWS: [(ClickType, &str); ClickType::COUNT] = [
+            (ClickType::HardClick, "C5"),
+            (ClickType::Click, "A4"),
+            (ClickType::SoftClick, "G4"),
+            (ClickType::MicroClick, "E4"),
+            (ClickType::HardRelease, "C4"),
+            (ClickType::Release, "A3"),
+            (ClickType::SoftRelease, "G3"),
+            (ClickType::MicroRelease, "E3"),
+        ];
+
+        let (width, height) = (width as f64, height as f64);
+        let row_height = height / ROWS.len() as f64;
+        let roll_width = (width - LABEL_WIDTH).max(1.0);
+        let x_of = |time: f64| {
+            LABEL_WIDTH
+                + if self.duration > 0.0 {
+                    time.clamp(0.0, self.duration) / self.duration * roll_width
+                } else {
+                    0.0
+                }
+        };
+
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        )?;
+        writeln!(
+            writer,
+            r##"<rect width="{width}" height="{height}" fill="#181818"/>"##
+        )?;
+        for (i, (typ, note)) in ROWS.iter().enumerate() {
+            let y = i as f64 * row_height;
+            if i % 2 == 1 {
+                writeln!(
+                    writer,
+                    r##"<rect x="{LABEL_WIDTH}" y="{y:.2}" width="{roll_width:.2}" height="{row_height:.2}" fill="#202020"/>"##
+                )?;
+            }
+            writeln!(
+                writer,
+                r##"<text x="4" y="{:.2}" fill="#cccccc" font-family="sans-serif" font-size="{:.1}">{note} {typ:?}</text>"##,
+                y + row_height * 0.7,
+                (row_height * 0.5).min(14.0)
+            )?;
+        }
+
+        for (i, action) in self.actions.iter().enumerate() {
+            let typ = action.click.click_type();
+            let Some(row) = ROWS.iter().position(|&(t, _)| t == typ) else {
+                continue;
+            };
+            let end = if action.click.is_click() {
+                // hold until the release of the same player and button
+                self.actions[i + 1..]
+                    .iter()
+                    .find(|a| {
+                        a.player == action.player
+                            && a.click.button() == action.click.button()
+                            && a.click.is_release()
+                    })
+                    .map_or(self.duration, |a| a.time)
+            } else {
+                action.time + RELEASE_LENGTH
+            };
+            let x = x_of(action.time);
+            writeln!(
+                writer,
+                r#"<rect x="{x:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="{}" fill-opacity="0.8"/>"#,
+                row as f64 * row_height + 1.0,
+                (x_of(end) - x).max(1.0),
+                (row_height - 2.0).max(1.0),
+                PLAYER_COLORS[(action.player == Player::Two) as usize]
+            )?;
+        }
+        writeln!(writer, "</svg>")?;
+        Ok(())
+    }
+
+    /// Writes a self-contained HTML report: an 800x400 SVG timeline of the actions colored
+    /// by click type (one lane per player, clicks on the top half and releases on the bottom
+    /// half of each lane), a statistics table and an animated click density chart. Actions
+    /// on the same pixel are merged, so the file stays small for long replays.
+    pub fn export_as_html_visualisation<W: Write>(&self, mut writer: W) -> Result<()> {
+        const WIDTH: usize = 800;
+        const HEIGHT: usize = 400;
+        const AXIS_HEIGHT: usize = 20;
+        const DENSITY_BUCKETS: usize = 100;
+        /// Colors of the click types, in [`ClickType::to_index`] order.
+        const COLORS: [&str; ClickType::COUNT] = [
+            "#ff4040", "#a02828", "#40c040", "#287828", "#4080ff", "#2850a0", "#e0c040", "#807020",
+        ];
+        const LANE_COLORS: [&str; 2] = ["#20203a", "#3a2020"];
+
+        let lane_height = (HEIGHT - AXIS_HEIGHT) / 2;
+        let column = |time: f64| {
+            if self.duration > 0.0 {
+                ((time / self.duration * WIDTH as f64) as usize).min(WIDTH - 1)
+            } else {
+                0
+            }
+        };
+
+        // one path per player and click type, with one vertical segment per pixel column
+        let mut columns = vec![HashSet::new(); 2 * ClickType::COUNT];
+        for action in &self.actions {
+            let typ = action.click.click_type();
+            if typ == ClickType::None {
+                continue;
+            }
+            let player = (action.player == Player::Two) as usize;
+            columns[player * ClickType::COUNT + typ.to_index()].insert(column(action.time));
+        }
+
+        writeln!(writer, "<!DOCTYPE html>")?;
+        writeln!(writer, r#"<html lang="en"><head><meta charset="utf-8">"#)?;
+        writeln!(writer, "<title>zcb3 replay report</title>")?;
+        writeln!(writer, "<style>")?;
+        writeln!(
+            writer,
+            "body{{background:#111;color:#ddd;font-family:sans-serif;margin:20px}}\n\
+            table{{border-collapse:collapse;margin:16px 0}}\n\
+            td,th{{border:1px solid #444;padding:4px 10px;text-align:left}}\n\
+            .density{{display:flex;align-items:flex-end;width:{WIDTH}px;height:120px;gap:1px}}\n\
+            .density div{{flex:1;background:#40c040;transform-origin:bottom;\
+            animation:grow 1s ease-out both}}\n\
+            @keyframes grow{{from{{transform:scaleY(0)}}to{{transform:scaleY(1)}}}}"
+        )?;
+        writeln!(writer, "</style></head><body>")?;
+        writeln!(writer, "<h1>Replay report</h1>")?;
+
+        // timeline
+        writeln!(
+            writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}">"#
+        )?;
+        for (player, color) in LANE_COLORS.iter().enumerate() {
+            writeln!(
+                writer,
+                r#"<rect x="0" y="{}" width="{WIDTH}" height="{lane_height}" fill="{color}"/>"#,
+                player * lane_height
+            )?;
+            writeln!(
+                writer,
+                r##"<text x="4" y="{}" fill="#888" font-size="12">Player {}</text>"##,
+                player * lane_height + 14,
+                player + 1
+            )?;
+        }
+        for (i, cols) in columns.iter().enumerate() {
+            if cols.is_empty() {
+                continue;
+            }
+            let (player, typ) = (
+                i / ClickType::COUNT,
+                ClickType::from_index(i % ClickType::COUNT),
+            );
+            let top = player * lane_height + if typ.is_release() { lane_height / 2 } else { 0 };
+            let mut cols: Vec<usize> = cols.iter().copied().collect();
+            cols.sort_unstable();
+            write!(writer, r#"<path stroke="{}" d=""#, COLORS[typ.to_index()])?;
+            for col in cols {
+                write!(writer, "M{col} {top}v{}", lane_height / 2)?;
+            }
+            writeln!(writer, r#""><title>{typ:?}</title></path>"#)?;
+        }
+        // time axis, at most ~10 ticks
+        let step = (self.duration / 10.0).ceil().max(1.0);
+        let mut tick = 0.0;
+        while tick <= self.duration {
+            let x = column(tick);
+            writeln!(
+                writer,
+                r##"<line x1="{x}" y1="{0}" x2="{x}" y2="{1}" stroke="#888"/><text x="{x}" y="{2}" fill="#888" font-size="10">{tick}s</text>"##,
+                HEIGHT - AXIS_HEIGHT,
+                HEIGHT - AXIS_HEIGHT + 5,
+                HEIGHT - 4
+            )?;
+            tick += step;
+        }
+        writeln!(writer, "</svg>")?;
+
+        // legend
+        write!(writer, "<p>")?;
+        for (i, color) in COLORS.iter().enumerate() {
+            write!(
+                writer,
+                r#"<span style="color:{color}">&#9632; {:?}</span> "#,
+                ClickType::from_index(i)
+            )?;
+        }
+        writeln!(writer, "</p>")?;
+
+        // statistics
+        let stats = self.input_stats_by_player();
+        writeln!(writer, "<table>")?;
+        for (name, value) in [
+            ("FPS", format!("{:.2}", self.fps)),
+            ("Duration", format!("{:.2}s", self.duration)),
+            ("Actions", self.actions.len().to_string()),
+            (
+                "Presses (P1 / P2)",
+                format!("{} / {}", stats[0].presses, stats[1].presses),
+            ),
+            (
+                "Releases (P1 / P2)",
+                format!("{} / {}", stats[0].releases, stats[1].releases),
+            ),
+            (
+                "Action rate",
+                format!("{:.0} BPM", self.average_action_rate_bpm()),
+            ),
+            (
+                "Timing entropy",
+                format!("{:.2} bits", self.compute_entropy()),
+            ),
+            ("Restarts", self.detect_level_restarts().len().to_string()),
+        ] {
+            writeln!(writer, "<tr><th>{name}</th><td>{value}</td></tr>")?;
+        }
+        writeln!(writer, "</table>")?;
+
+        // click density
+        let mut density = [0usize; DENSITY_BUCKETS];
+        for action in self.actions.iter().filter(|a| a.click.is_click()) {
+            density[column(action.time) * DENSITY_BUCKETS / WIDTH] += 1;
+        }
+        let max_density = density.iter().copied().max().unwrap_or(0).max(1);
+        writeln!(writer, "<h2>Click density</h2>")?;
+        writeln!(writer, r#"<div class="density">"#)?;
+        for (i, count) in density.iter().enumerate() {
+            writeln!(
+                writer,
+                r#"<div style="height:{:.1}%;animation-delay:{}ms" title="{count} clicks"></div>"#,
+                *count as f64 / max_density as f64 * 100.0,
+                i * 10
+            )?;
+        }
+        writeln!(writer, "</div>")?;
+        writeln!(writer, "</body></html>")?;
+        Ok(())
+    }
+
+    /// Writes a REAPER region list (`#,Name,Start,End,Color`) with one 50 ms region per
+    /// click, colored by click type.
+    pub fn export_as_reaper_region_file<W: Write>(&self, mut writer: W) -> Result<()> {
+        const REGION_DURATION: f64 = 0.05;
+        writeln!(writer, "#,Name,Start,End,Color")?;
+        let clicks = self.actions.iter().filter(|a| a.click.is_click());
+        for (i, action) in clicks.enumerate() {
+            let color = match action.click.click_type() {
+                ClickType::HardClick => "FF4040",
+                ClickType::Click => "40C040",
+                ClickType::SoftClick => "4080FF",
+                _ => "C0C040",
+            };
+            writeln!(
+                writer,
+                "R{},P{} Click,{:.6},{:.6},{color}",
+                i + 1,
+                if action.player == Player::One { 1 } else { 2 },
+                action.time,
+                action.time + REGION_DURATION
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes a VCV Rack sequence as CSV (`step,voltage,gate_ms,rest_ms`) with one step per
+    /// action. Hard, regular, soft and micro clicks are 10, 7.5, 5 and 2.5 V, releases
+    /// are the same but negative. The rest is the time until the next action.
+    pub fn export_as_vcv_rack_sequence<W: Write>(&self, mut writer: W) -> Result<()> {
+        const GATE_MS: f64 = 100.0;
+        writeln!(writer, "step,voltage,gate_ms,rest_ms")?;
+        for (i, action) in self.actions.iter().enumerate() {
+            let typ = action.click.click_type();
+            // clicks and releases alternate in the click type index order
+            let voltage = 10.0 - (typ.to_index() / 2) as f64 * 2.5;
+            let voltage = if typ.is_release() { -voltage } else { voltage };
+            let rest_ms = self
+                .actions
+                .get(i + 1)
+                .map_or(0.0, |next| (next.time - action.time) * 1000.0);
+            writeln!(writer, "{},{voltage:.2},{GATE_MS:.0},{rest_ms:.3}", i + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Writes an NSF (NES Sound Format) file that plays a noise channel click on every
+    /// action. The player is a fixed 6502 routine called at 60 Hz (NTSC), which reads the
+    /// delays between clicks from a timing table: a byte `n < 0xFE` clicks after `n + 1`
+    /// ticks, `0xFE` waits 254 ticks without clicking and `0xFF` ends the song.
+    pub fn export_as_nes_nsf<W: Write>(&self, mut writer: W) -> Result<()> {
+        const LOAD_ADDR: u16 = 0x8000;
+        /// Play routine period in microseconds (NTSC).
+        const NTSC_SPEED: u16 = 16639;
+        const PAL_SPEED: u16 = 19997;
+        /// Assembled player. Zero page: $00-$01 table pointer, $02 ticks until the next
+        /// event, $03 whether the next event clicks.
+        #[rustfmt::skip]
+        const PROGRAM: [u8; 0x54] = [
+            // init ($8000)
+            0xA9, 0x54,       // LDA #<table
+            0x85, 0x00,       // STA $00
+            0xA9, 0x80,       // LDA #>table
+            0x85, 0x01,       // STA $01
+            0xA9, 0x08,       // LDA #$08
+            0x8D, 0x15, 0x40, // STA $4015 ; enable the noise channel
+            0xA9, 0x00,       // LDA #0
+            0x85, 0x02,       // STA $02   ; load the first delay on the first tick
+            0x85, 0x03,       // STA $03
+            0x60,             // RTS
+            // play ($8014)
+            0xA5, 0x02,       // LDA $02
+            0xF0, 0x03,       // BEQ event
+            0xC6, 0x02,       // DEC $02
+            0x60,             // RTS
+            // event:
+            0xA5, 0x03,       // LDA $03
+            0xF0, 0x0F,       // BEQ next
+            0xA9, 0x1F,       // LDA #$1F
+            0x8D, 0x0C, 0x40, // STA $400C ; constant volume 15
+            0xA9, 0x03,       // LDA #$03
+            0x8D, 0x0E, 0x40, // STA $400E ; noise period 3
+            0xA9, 0x28,       // LDA #$28
+            0x8D, 0x0F, 0x40, // STA $400F ; short length counter, starts the click
+            // next:
+            0xA0, 0x00,       // LDY #0
+            0xB1, 0x00,       // LDA ($00),Y
+            0xC9, 0xFF,       // CMP #$FF
+            0xF0, 0x15,       // BEQ end
+            0xA2, 0x01,       // LDX #1
+            0xC9, 0xFE,       // CMP #$FE
+            0xD0, 0x04,       // BNE store
+            0xA2, 0x00,       // LDX #0
+            0xA9, 0xFD,       // LDA #$FD
+            // store:
+            0x86, 0x03,       // STX $03
+            0x85, 0x02,       // STA $02
+            0xE6, 0x00,       // INC $00
+            0xD0, 0x02,       // BNE done
+            0xE6, 0x01,       // INC $01
+            // done:
+            0x60,             // RTS
+            // end:
+            0xA9, 0x00,       // LDA #0
+            0x85, 0x03,       // STA $03
+            0xA9, 0xFF,       // LDA #$FF
+            0x85, 0x02,       // STA $02
+            0x60,             // RTS
+        ];
+        const PLAY_ADDR: u16 = LOAD_ADDR + 0x14;
+
+        // timing table, one delay per tick with a click
+        let mut table = vec![];
+        let mut prev_tick = 0;
+        for action in &self.actions {
+            let tick = (action.time * 1_000_000.0 / NTSC_SPEED as f64).round() as u64;
+            if !table.is_empty() && tick <= prev_tick {
+                continue; // one click per tick
+            }
+            let mut delay = (tick - prev_tick).max(1);
+            while delay > 254 {
+                table.push(0xFE);
+                delay -= 254;
+            }
+            table.push(delay as u8 - 1);
+            // the first tick only loads the first delay
+            prev_tick = tick.max(1);
+        }
+        table.push(0xFF);
+        let max_len = 0x10000 - (LOAD_ADDR as usize + PROGRAM.len());
+        if table.len() > max_len {
+            anyhow::bail!(
+                "replay is too long for an NSF file ({} > {max_len} timing table bytes)",
+                table.len()
+            );
+        }
+
+        let mut header = [0u8; 0x80];
+        header[..5].copy_from_slice(b"NESM\x1a");
+        header[5] = 1; // version
+        header[6] = 1; // total songs
+        header[7] = 1; // starting song
+        header[8..10].copy_from_slice(&LOAD_ADDR.to_le_bytes());
+        header[0x0A..0x0C].copy_from_slice(&LOAD_ADDR.to_le_bytes()); // init
+        header[0x0C..0x0E].copy_from_slice(&PLAY_ADDR.to_le_bytes());
+        let title = b"ZCB3 replay";
+        header[0x0E..0x0E + title.len()].copy_from_slice(title);
+        header[0x6E..0x70].copy_from_slice(&NTSC_SPEED.to_le_bytes());
+        // 0x70..0x78: no bankswitching
+        header[0x78..0x7A].copy_from_slice(&PAL_SPEED.to_le_bytes());
+
+        writer.write_all(&header)?;
+        writer.write_all(&PROGRAM)?;
+        writer.write_all(&table)?;
+        Ok(())
+    }
+
+    /// Writes the actions as Clickteam Fusion timing events, one `time_ms,event_type,player`
+    /// line per action. `event_type` is 1 for presses and 0 for releases, `player` is 1 or 2
+    /// and the time is rounded to the nearest millisecond.
+    pub fn export_as_clickteam_event<W: Write>(&self, mut writer: W) -> Result<()> {
+        for action in &self.actions {
+            writeln!(
+                writer,
+                "{},{},{}",
+                (action.time * 1000.0).round() as i64,
+                action.click.is_click() as u8,
+                if action.player == Player::One { 1 } else { 2 }
+            )?;
         }
+        Ok(())
     }
 
-    fn extended_p2(&mut self, down: bool, frame: u32, x: f32, y: f32, y_accel: f32, rot: f32) {
-        if self.extended_data {
-            // if x is 0.0, try to get the x position from the first player
-            // FIXME: we probably shouldn't do this for converting replays
-            let x = if x == 0. {
-                if let Some(last) = self.get_last_extended(Player::One) {
-                    last.x
-                } else {
-                    x
-                }
-            } else {
-                x
-            };
+    /// Writes an Audacity label track: one label per action, starting at the action time
+    /// and lasting 100 ms, named after the click type.
+    pub fn export_as_audacity_labels<W: Write>(&self, mut writer: W) -> Result<()> {
+        const LABEL_DURATION: f64 = 0.1;
+        for action in &self.actions {
+            writeln!(
+                writer,
+                "{:.6}\t{:.6}\t{:?}",
+                action.time,
+                action.time + LABEL_DURATION,
+                action.click.click_type()
+            )?;
+        }
+        Ok(())
+    }
 
-            self.extended.push(ExtendedAction {
-                player2: true,
-                down,
-                frame,
-                x,
-                y,
-                y_accel,
-                rot,
-                fps_change: None,
-            });
+    /// Writes the physics data of a player as CSV keyframes (`time,x,y,rot`) that can be
+    /// imported into Blender.
+    pub fn export_as_blender_csv<W: Write>(&self, mut writer: W, player: Player) -> Result<()> {
+        // GD is Y-up and 2D, Blender is Z-up: GD X maps to Blender X, GD Y maps to
+        // Blender Z and the rotation (degrees, clockwise) is around Blender's Y axis
+        writeln!(
+            writer,
+            "# GD X -> Blender X, GD Y -> Blender Z, rot = degrees around Blender Y (clockwise)"
+        )?;
+        writeln!(writer, "time,x,y,rot")?;
+        let player2 = player == Player::Two;
+        for e in self.extended.iter().filter(|e| e.player2 == player2) {
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                e.frame as f64 / self.fps,
+                e.x,
+                e.y,
+                e.rot
+            )?;
         }
+        Ok(())
     }
 
-    fn get_last_extended(&self, player: Player) -> Option<ExtendedAction> {
-        // iterate from the back and find the last action for this player
-        for action in self.extended.iter().rev() {
-            if player != Player::Two && action.player2 {
+    /// Writes one CSV row per action (`time_seconds,frame,player,click_type,vol_offset`),
+    /// followed by the interpolated `x,y,y_accel,rot` of the player if the replay has
+    /// extended data.
+    pub fn export_csv<W: Write>(&self, mut writer: W) -> Result<()> {
+        write!(writer, "time_seconds,frame,player,click_type,vol_offset")?;
+        if self.extended_data {
+            write!(writer, ",x,y,y_accel,rot")?;
+        }
+        writeln!(writer)?;
+        for action in &self.actions {
+            write!(
+                writer,
+                "{},{},{},{:?},{}",
+                action.time,
+                action.frame,
+                action.player as u8 + 1,
+                action.click.click_type(),
+                action.vol_offset
+            )?;
+            if self.extended_data {
+                let e = self.sample_extended_at(action.time, action.player);
+                write!(writer, ",{},{},{},{}", e.x, e.y, e.y_accel, e.rot)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes the replay as a zBot frame (`.zbf`) file: f32 delta and f32 speedhack,
+    /// followed by a 6 byte record (i32 frame, down, player 1) per action. The speedhack
+    /// is already part of the framerate, so it is always written as 1. Platformer
+    /// actions can't be stored and are skipped.
+    pub fn export_zbf(&self) -> Result<Vec<u8>> {
+        if self.fps <= 0.0 || self.fps.is_nan() {
+            anyhow::bail!("invalid replay framerate: {}", self.fps);
+        }
+        // a zero speedhack would make the framerate infinite when parsing
+        let speedhack = 1.0f32;
+        let delta = (1.0 / self.fps) as f32;
+
+        let mut data = Vec::with_capacity(8 + self.actions.len() * 6);
+        data.extend_from_slice(&delta.to_le_bytes());
+        data.extend_from_slice(&speedhack.to_le_bytes());
+        for action in &self.actions {
+            if !matches!(action.click, Click::Regular(_)) {
                 continue;
             }
-            return Some(*action);
+            let frame = i32::try_from(action.frame)
+                .map_err(|_| anyhow::anyhow!("frame {} doesn't fit in a zbf file", action.frame))?;
+            data.extend_from_slice(&frame.to_le_bytes());
+            data.push(if action.click.is_click() { 0x31 } else { 0x30 });
+            data.push(if action.player == Player::One {
+                0x31
+            } else {
+                0x30
+            });
         }
-        None
+        Ok(data)
     }
 
-    pub fn filter_actions<F>(&self, player: Player, func: F)
-    where
-        F: FnMut(&ExtendedAction),
-    {
-        self.extended
+    /// Encodes the replay with [`Replay::export_zbf`] and writes it to `path`.
+    pub fn export_zbf_to_file(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.export_zbf()?)?;
+        Ok(())
+    }
+
+    /// Writes the replay as a yBot 2 macro (`.ybot`).
+    pub fn to_ybot2<W: Read + Write + Seek>(&self, writer: W) -> Result<()> {
+        use ybot_fmt::{Action as YbotAction, Macro, Meta, PlayerButton, TimedAction};
+
+        let mut actions: Vec<&Action> = self.actions.iter().collect();
+        actions.sort_by_key(|a| a.frame);
+        let presses = actions.iter().filter(|a| a.click.is_click()).count() as u64;
+        let frames = actions.last().map_or(0, |a| a.frame as u64 + 1);
+
+        let mut m = Macro::create(writer)?;
+        m.set(Meta::DATE, chrono::Utc::now().timestamp())?;
+        m.set(Meta::PRESSES, presses)?;
+        m.set(Meta::FRAMES, frames)?;
+        m.set(Meta::FPS, self.fps as f32)?;
+        m.set(Meta::TOTAL_PRESSES, presses)?;
+
+        // fps changes are stored in the extended action that came before them
+        let mut fps_changes = self
+            .extended
             .iter()
-            .filter(|a| {
-                (a.player2 && player == Player::Two) || (!a.player2 && player == Player::One)
-            })
-            .for_each(func)
+            .filter_map(|e| e.fps_change.map(|fps| (e.frame as u64, fps)))
+            .peekable();
+        let mut prev_frame = 0u64;
+        for action in actions {
+            let frame = action.frame as u64;
+            while let Some((fps_frame, fps)) = fps_changes.next_if(|(f, _)| *f < frame) {
+                m.add(TimedAction::new(
+                    fps_frame - prev_frame,
+                    YbotAction::FPS(fps as f32),
+                ))?;
+                prev_frame = fps_frame;
+            }
+
+            let button = match action.click.button() {
+                2 => PlayerButton::Left,
+                3 => PlayerButton::Right,
+                _ => PlayerButton::Jump,
+            };
+            m.add(TimedAction::new(
+                frame - prev_frame,
+                YbotAction::Button(
+                    action.player == Player::One,
+                    action.click.is_click(),
+                    button,
+                ),
+            ))?;
+            prev_frame = frame;
+        }
+        for (fps_frame, fps) in fps_changes {
+            m.add(TimedAction::new(
+                fps_frame.saturating_sub(prev_frame),
+                YbotAction::FPS(fps as f32),
+            ))?;
+            prev_frame = prev_frame.max(fps_frame);
+        }
+        Ok(())
     }
 
     /// Returns the last frame in the replay. If extended actions are disabled, this
@@ -1505,15 +3809,7 @@ impl Replay {
             reader.read_exact(&mut buf)?;
             let correction: Correction = unsafe { *(buf.as_ptr() as *const Correction) };
             let frame = (correction.time * self.fps as f64) as u32;
-            let push = self
-                .actions
-                .binary_search_by(|a| a.frame.cmp(&frame))
-                .unwrap_or(0);
-            let push = self
-                .actions
-                .get(push)
-                .map(|a| a.click.is_click())
-                .unwrap_or(false);
+            let push = self.is_click_at_frame(frame);
 
             if correction.player1 {
                 self.extended_p1(
@@ -2285,12 +4581,8 @@ impl Replay {
             let y = d.read_f32::<LittleEndian>()?;
             let rot = d.read_f32::<LittleEndian>()?;
 
-            // find action to get hold state (FIXME: very inefficent)
-            let hold = if let Ok(idx) = self.actions.binary_search_by(|a| a.frame.cmp(&frame)) {
-                self.actions[idx].click.is_click()
-            } else {
-                false
-            };
+            // find action to get hold state
+            let hold = self.is_click_at_frame(frame);
 
             if p2 {
                 self.extended_p2(hold, frame, x, y, 0.0, rot);
@@ -2425,14 +4717,7 @@ impl Replay {
             }
 
             // find button state
-            let push = if let Ok(idx) = self.actions.binary_search_by(|a| a.frame.cmp(&fix.frame)) {
-                self.actions
-                    .get(idx)
-                    .map(|a| a.click.is_click())
-                    .unwrap_or(false)
-            } else {
-                false
-            };
+            let push = self.is_click_at_frame(fix.frame);
 
             self.extended_p1(
                 push,
@@ -2627,3 +4912,882 @@ impl Replay {
     }
     */
 }
+
+/// Encodes `data` as standard (padded) base64.
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes standard base64, ignoring whitespace and padding.
+fn base64_decode(encoded: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(encoded.len() / 4 * 3);
+    let (mut buf, mut bits) = (0u32, 0);
+    for c in encoded.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => continue,
+            c if c.is_ascii_whitespace() => continue,
+            _ => anyhow::bail!("invalid base64 character '{}'", c as char),
+        };
+        buf = (buf << 6 | value as u32) & 0xFFFF;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buf >> bits) as u8);
+        }
+    }
+    Ok(data)
+}
+
+/// Appends `value` as a MIDI variable-length quantity.
+fn write_vlq(buf: &mut Vec<u8>, value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(frame: u32, player: Player, click: Click) -> Action {
+        Action::new(frame as f64 / 240.0, player, click, 0.0, frame)
+    }
+
+    #[test]
+    fn test_click_type_index() {
+        for idx in 0..ClickType::COUNT {
+            let typ = ClickType::from_index(idx);
+            assert_ne!(typ, ClickType::None);
+            assert_eq!(typ.to_index(), idx);
+            assert_eq!(ClickType::from_index(typ.to_index()), typ);
+        }
+        assert_eq!(ClickType::None.to_index(), ClickType::COUNT);
+        assert_eq!(ClickType::from_index(ClickType::COUNT), ClickType::None);
+    }
+
+    #[test]
+    fn test_sort_extended_stable() {
+        let extended = |frame, player2, x| ExtendedAction {
+            frame,
+            player2,
+            x,
+            ..Default::default()
+        };
+        let mut replay = Replay {
+            extended: vec![
+                extended(5, true, 0.0),
+                extended(5, false, 1.0),
+                extended(1, true, 2.0),
+                extended(5, false, 3.0),
+                extended(1, false, 4.0),
+            ],
+            ..Default::default()
+        };
+        replay.sort_extended_stable();
+
+        let order: Vec<(u32, bool, f32)> = replay
+            .extended
+            .iter()
+            .map(|e| (e.frame, e.player2, e.x))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (1, false, 4.0),
+                (1, true, 2.0),
+                (5, false, 1.0),
+                (5, false, 3.0),
+                (5, true, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_actions_within_frame_range() {
+        let replay = Replay {
+            actions: vec![
+                action(10, Player::One, Click::Regular(ClickType::Click)),
+                action(20, Player::One, Click::Regular(ClickType::Release)),
+                action(20, Player::Two, Click::Regular(ClickType::Click)),
+                action(30, Player::Two, Click::Regular(ClickType::Release)),
+            ],
+            ..Default::default()
+        };
+        let frames = |start, end| -> Vec<u32> {
+            replay
+                .actions_within_frame_range_inclusive(start, end)
+                .iter()
+                .map(|a| a.frame)
+                .collect()
+        };
+
+        // empty ranges
+        assert!(frames(11, 19).is_empty());
+        assert!(frames(20, 10).is_empty());
+        assert!(Replay::default()
+            .actions_within_frame_range_inclusive(0, u32::MAX)
+            .is_empty());
+
+        // ranges beyond the replay
+        assert!(frames(31, 100).is_empty());
+        assert_eq!(frames(0, u32::MAX), vec![10, 20, 20, 30]);
+
+        // exactly one action
+        assert_eq!(frames(10, 10), vec![10]);
+        assert_eq!(frames(25, 35), vec![30]);
+
+        assert_eq!(frames(20, 20), vec![20, 20]);
+    }
+
+    #[test]
+    fn test_ybot2_round_trip() {
+        let mut replay = Replay {
+            fps: 240.0,
+            ..Default::default()
+        };
+        replay.actions = vec![
+            action(10, Player::One, Click::Regular(ClickType::Click)),
+            action(20, Player::One, Click::Regular(ClickType::Release)),
+            action(20, Player::Two, Click::Left(ClickType::Click)),
+            action(35, Player::Two, Click::Left(ClickType::Release)),
+            action(50, Player::One, Click::Right(ClickType::Click)),
+        ];
+
+        let mut buf = Cursor::new(Vec::new());
+        replay.to_ybot2(&mut buf).unwrap();
+        buf.set_position(0);
+        let parsed = Replay::build()
+            .with_extended(true)
+            .parse(ReplayType::Ybot2, buf)
+            .unwrap();
+
+        assert_eq!(parsed.fps, 240.0);
+        assert_eq!(parsed.actions.len(), replay.actions.len());
+        for (a, b) in replay.actions.iter().zip(&parsed.actions) {
+            assert_eq!(a.frame, b.frame);
+            assert_eq!(a.player, b.player);
+            assert_eq!(a.click.button(), b.click.button());
+            assert_eq!(a.click.is_click(), b.click.is_click());
+        }
+    }
+
+    #[test]
+    fn test_base64_round_trip() {
+        for data in [&b""[..], b"a", b"ab", b"abc", b"push1"] {
+            assert_eq!(base64_decode(&base64_encode(data)).unwrap(), data);
+        }
+        assert_eq!(base64_encode(b"push1"), "cHVzaDE=");
+        assert!(base64_decode("not base64!").is_err());
+
+        let replay = Replay {
+            fps: 240.0,
+            actions: vec![
+                action(10, Player::One, Click::Regular(ClickType::Click)),
+                action(20, Player::Two, Click::Regular(ClickType::Click)),
+                action(30, Player::One, Click::Regular(ClickType::Release)),
+            ],
+            ..Default::default()
+        };
+        for format in [gdr::GdrFormat::Json, gdr::GdrFormat::MsgPack] {
+            let encoded = replay.to_base64_string(format).unwrap();
+            let parsed = Replay::build().import_from_base64_string(&encoded).unwrap();
+            let frames: Vec<u32> = parsed.actions.iter().map(|a| a.frame).collect();
+            assert_eq!(frames, [10, 20, 30]);
+        }
+    }
+
+    #[test]
+    fn test_export_as_stepmania_sm() {
+        // at 60 BPM one beat is one second, so 240 frames
+        let replay = Replay {
+            fps: 240.0,
+            actions: vec![
+                action(0, Player::One, Click::Regular(ClickType::Click)),
+                action(240, Player::One, Click::Regular(ClickType::Release)),
+                action(960, Player::Two, Click::Regular(ClickType::Click)),
+            ],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_stepmania_sm(&mut out, 60.0).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let notes = out.split("0,0,0,0,0:\n").nth(1).unwrap();
+        assert_eq!(
+            notes,
+            "1000\n0100\n0000\n0000\n,\n0010\n0000\n0000\n0000\n;\n"
+        );
+    }
+
+    #[test]
+    fn test_replay_type_from_magic() {
+        assert!(matches!(
+            ReplayType::from_magic(b"ybot\x01\x00"),
+            Some(ReplayType::Ybot2)
+        ));
+        assert!(matches!(
+            ReplayType::from_magic(b"RE2\x00"),
+            Some(ReplayType::ReplayEngine2)
+        ));
+        assert!(ReplayType::from_magic(b"{\"fps\": 240}").is_none());
+        assert!(ReplayType::from_magic(b"HA").is_none());
+    }
+
+    #[test]
+    fn test_split_by_death() {
+        let extended = |frame: u32, x: f32| ExtendedAction {
+            frame,
+            x,
+            ..Default::default()
+        };
+        let replay = Replay {
+            fps: 240.0,
+            duration: 2.0,
+            actions: vec![
+                action(10, Player::One, Click::Regular(ClickType::Click)),
+                action(250, Player::One, Click::Regular(ClickType::Click)),
+                action(300, Player::One, Click::Regular(ClickType::Release)),
+            ],
+            extended: vec![extended(10, 500.0), extended(240, 0.0), extended(300, 50.0)],
+            ..Default::default()
+        };
+        let attempts = replay.split_by_death();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].actions.len(), 1);
+        assert_eq!(attempts[0].duration, 1.0);
+        let frames: Vec<u32> = attempts[1].actions.iter().map(|a| a.frame).collect();
+        assert_eq!(frames, [10, 60]);
+        assert!((attempts[1].actions[0].time - 10.0 / 240.0).abs() < 1e-9);
+        assert_eq!(attempts[1].extended[0].frame, 0);
+        assert_eq!(attempts[1].duration, 1.0);
+    }
+
+    #[test]
+    fn test_export_as_ogg_chapters() {
+        let replay = Replay {
+            fps: 240.0,
+            duration: 70.0,
+            extended: vec![
+                ExtendedAction {
+                    frame: 240,
+                    x: 500.0,
+                    ..Default::default()
+                },
+                ExtendedAction {
+                    frame: 240 * 65 + 12,
+                    fps_change: Some(360.0),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_ogg_chapters(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "CHAPTER001=00:00:00.000\nCHAPTER001NAME=Start\n\
+             CHAPTER002=00:01:05.050\nCHAPTER002NAME=Death 1\n\
+             CHAPTER003=00:01:05.050\nCHAPTER003NAME=FPS change to 360\n"
+        );
+    }
+
+    #[test]
+    fn test_export_as_nes_nsf() {
+        let tick = 16639.0 / 1_000_000.0;
+        let replay = Replay {
+            actions: [0.0, 0.0, 10.0 * tick, 310.0 * tick]
+                .iter()
+                .map(|&time| {
+                    Action::new(time, Player::One, Click::Regular(ClickType::Click), 0.0, 0)
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_nes_nsf(&mut out).unwrap();
+        assert_eq!(&out[..5], b"NESM\x1a");
+        assert_eq!(&out[8..14], [0x00, 0x80, 0x00, 0x80, 0x14, 0x80]);
+        // the table starts after the header and the 0x54 byte player
+        assert_eq!(out.len(), 0x80 + 0x54 + 5);
+        assert_eq!(&out[0x80 + 0x54..], [0, 8, 0xFE, 45, 0xFF]);
+    }
+
+    #[test]
+    fn test_compute_reaction_time_distribution() {
+        let extended = |frame: u32, y_accel: f32| ExtendedAction {
+            frame,
+            y_accel,
+            ..Default::default()
+        };
+        let replay = Replay {
+            fps: 240.0,
+            actions: vec![
+                action(0, Player::One, Click::Regular(ClickType::Click)),
+                action(24, Player::One, Click::Regular(ClickType::Release)),
+                action(120, Player::One, Click::Regular(ClickType::Click)),
+            ],
+            // apex halfway between frames 24 and 72
+            extended: vec![extended(0, 10.0), extended(24, 5.0), extended(72, -5.0)],
+            ..Default::default()
+        };
+        let reaction_times = replay.compute_reaction_time_distribution();
+        assert_eq!(reaction_times.len(), 1);
+        assert!((reaction_times[0] - 72.0 / 240.0).abs() < 1e-9);
+        assert!(Replay::default()
+            .compute_reaction_time_distribution()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_export_as_smil() {
+        let replay = Replay {
+            duration: 2.0,
+            actions: vec![
+                action(240, Player::One, Click::Regular(ClickType::HardClick)),
+                action(240, Player::Two, Click::Regular(ClickType::Click)),
+                action(300, Player::One, Click::Regular(ClickType::Release)),
+            ],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_smil(&mut out).unwrap();
+        let smil = String::from_utf8(out).unwrap();
+        assert_eq!(smil.matches("<audio ").count(), 3);
+        assert!(smil.contains(
+            r#"<audio src="clicks.wav" begin="1.000s" clipBegin="1.000s" clipEnd="1.250s" title="Click (player 2)"/>"#
+        ));
+        assert!(smil.contains(r#"clipBegin="1.250s" clipEnd="2.000s""#));
+    }
+
+    #[test]
+    fn test_to_sparse_matrix() {
+        let replay = Replay {
+            actions: vec![
+                action(0, Player::One, Click::Regular(ClickType::Click)),
+                action(3, Player::Two, Click::Right(ClickType::SoftClick)),
+                action(5, Player::One, Click::Regular(ClickType::Release)),
+                action(9, Player::Two, Click::Regular(ClickType::HardClick)),
+            ],
+            ..Default::default()
+        };
+        let row = |columns: &[usize]| (0..8).map(|i| columns.contains(&i)).collect::<Vec<_>>();
+        assert_eq!(
+            replay.to_sparse_matrix(4),
+            [row(&[0, 7]), row(&[1]), row(&[2])]
+        );
+        assert_eq!(replay.to_sparse_matrix(0).len(), 10);
+        assert!(Replay::default().to_sparse_matrix(1).is_empty());
+    }
+
+    #[test]
+    fn test_export_zbf() {
+        let replay = Replay {
+            fps: 240.0,
+            actions: vec![
+                action(10, Player::One, Click::Regular(ClickType::Click)),
+                action(12, Player::Two, Click::Regular(ClickType::Click)),
+                action(20, Player::One, Click::Left(ClickType::Click)),
+                action(30, Player::One, Click::Regular(ClickType::Release)),
+            ],
+            ..Default::default()
+        };
+        let data = replay.export_zbf().unwrap();
+        assert_eq!(data.len(), 8 + 3 * 6);
+        assert_eq!(&data[4..8], 1.0f32.to_le_bytes());
+        assert_eq!(&data[8..14], [10, 0, 0, 0, 0x31, 0x31]);
+
+        let parsed = Replay::build()
+            .parse_bytes(ReplayType::Zbot, &data)
+            .unwrap();
+        assert!((parsed.fps - 240.0).abs() < 1e-3);
+        let frames: Vec<(u32, Player, bool)> = parsed
+            .actions
+            .iter()
+            .map(|a| (a.frame, a.player, a.click.is_click()))
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                (10, Player::One, true),
+                (12, Player::Two, true),
+                (30, Player::One, false)
+            ]
+        );
+
+        let too_long = Replay {
+            fps: 240.0,
+            actions: vec![action(
+                u32::MAX,
+                Player::One,
+                Click::Regular(ClickType::Click),
+            )],
+            ..Default::default()
+        };
+        assert!(too_long.export_zbf().is_err());
+        assert!(Replay::default().export_zbf().is_err());
+    }
+
+    #[test]
+    fn test_annotate_with_game_events() {
+        let events: Vec<GameEvent> = serde_json::from_str(
+            r#"[{"frame": 300, "event_type": "CHECKPOINT"},
+                {"frame": 20, "event_type": "ORB_ACTIVATED"}]"#,
+        )
+        .unwrap();
+        let replay = Replay {
+            fps: 240.0,
+            duration: 2.0,
+            extended: vec![
+                ExtendedAction {
+                    frame: 10,
+                    x: 500.0,
+                    ..Default::default()
+                },
+                ExtendedAction {
+                    frame: 240,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+        .annotate_with_game_events(events);
+        let frames: Vec<u32> = replay.events.iter().map(|e| e.frame).collect();
+        assert_eq!(frames, [20, 300]);
+
+        // events are rebased onto their attempt
+        let attempts = replay.split_by_death();
+        assert_eq!(
+            attempts[0].events[0].event_type,
+            GameEventType::OrbActivated
+        );
+        assert_eq!(
+            attempts[1].events,
+            [GameEvent {
+                frame: 60,
+                event_type: GameEventType::Checkpoint
+            }]
+        );
+    }
+
+    #[test]
+    fn test_export_as_html_visualisation_size() {
+        // every frame of a 10 minute replay
+        let frames = 240 * 600;
+        let replay = Replay {
+            fps: 240.0,
+            duration: 600.0,
+            actions: (0..frames)
+                .map(|f| {
+                    let typ = ClickType::from_index(f as usize % ClickType::COUNT);
+                    let player = if f % 3 == 0 { Player::Two } else { Player::One };
+                    action(f, player, Click::Regular(typ))
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_html_visualisation(&mut out).unwrap();
+        assert!(out.len() < 500 * 1024, "report is {} bytes", out.len());
+        assert!(String::from_utf8(out).unwrap().contains("</svg>"));
+    }
+
+    #[test]
+    fn test_export_as_piano_roll_svg() {
+        let replay = Replay {
+            duration: 1.0,
+            actions: vec![
+                action(0, Player::One, Click::Regular(ClickType::HardClick)),
+                action(120, Player::Two, Click::Regular(ClickType::SoftClick)),
+                action(240, Player::One, Click::Regular(ClickType::Release)),
+            ],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay
+            .export_as_piano_roll_svg(&mut out, 1110, 800)
+            .unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains(">C5 HardClick</text>"));
+        // the hard click is held until the release at 1s
+        assert!(svg.contains(
+            r##"<rect x="110.00" y="1.00" width="1000.00" height="98.00" fill="#4080ff""##
+        ));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_compute_entropy() {
+        let replay_with_frames = |frames: &[u32]| Replay {
+            actions: frames
+                .iter()
+                .map(|&f| action(f, Player::One, Click::Regular(ClickType::Click)))
+                .collect(),
+            ..Default::default()
+        };
+        // constant intervals are fully predictable
+        assert_eq!(replay_with_frames(&[0, 24, 48, 72]).compute_entropy(), 0.0);
+        // 4 distinct intervals are 2 bits
+        let entropy = replay_with_frames(&[0, 24, 72, 144, 240]).compute_entropy();
+        assert!((entropy - 2.0).abs() < 1e-9);
+        assert_eq!(Replay::default().compute_entropy(), 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "json-schema")]
+    fn test_export_as_json_schema_validated() {
+        let replay = Replay {
+            fps: 240.0,
+            actions: vec![action(10, Player::One, Click::Regular(ClickType::Click))],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_json_schema_validated(&mut out).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value["$schema"], Replay::GDR_SCHEMA_URL);
+        assert_eq!(value["inputs"][0]["frame"], 10);
+
+        // replays without a framerate are still valid
+        let mut out = vec![];
+        Replay::default()
+            .export_as_json_schema_validated(&mut out)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_seeded_volume_variation() {
+        let replay = Replay {
+            fps: 240.0,
+            actions: vec![
+                action(10, Player::One, Click::Regular(ClickType::Click)),
+                action(12, Player::One, Click::Regular(ClickType::Release)),
+                action(20, Player::One, Click::Regular(ClickType::Click)),
+                action(30, Player::One, Click::Regular(ClickType::Release)),
+            ],
+            ..Default::default()
+        };
+        let data = replay.export_zbf().unwrap();
+        let vol_offsets = |seed| {
+            Replay::build()
+                .with_seed(seed)
+                .parse_bytes(ReplayType::Zbot, &data)
+                .unwrap()
+                .actions
+                .iter()
+                .map(|a| a.vol_offset)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vol_offsets(1), vol_offsets(1));
+        assert_ne!(vol_offsets(1), vol_offsets(2));
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let mut replay = Replay {
+            fps: 240.0,
+            actions: vec![
+                action(240, Player::One, Click::Regular(ClickType::HardClick)),
+                action(480, Player::Two, Click::Regular(ClickType::Release)),
+            ],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_csv(&mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "time_seconds,frame,player,click_type,vol_offset");
+        assert_eq!(lines[1], "1,240,1,HardClick,0");
+        assert_eq!(lines[2], "2,480,2,Release,0");
+
+        replay.extended_data = true;
+        replay.extended = vec![ExtendedAction {
+            frame: 240,
+            x: 5.0,
+            y: 3.0,
+            ..Default::default()
+        }];
+        let mut out = vec![];
+        replay.export_csv(&mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert!(lines[0].ends_with(",x,y,y_accel,rot"));
+        assert_eq!(lines[1], "1,240,1,HardClick,0,5,3,0,0");
+    }
+
+    #[test]
+    fn test_sample_extended_at_midpoint() {
+        let ext = |frame, x, y, down| ExtendedAction {
+            frame,
+            x,
+            y,
+            down,
+            ..Default::default()
+        };
+        let replay = Replay {
+            fps: 240.0,
+            extended: vec![
+                ext(10, 0.0, 10.0, true),
+                ExtendedAction {
+                    player2: true,
+                    ..ext(15, 100.0, 100.0, true)
+                },
+                ext(20, 10.0, 30.0, false),
+            ],
+            ..Default::default()
+        };
+        let e = replay.sample_extended_at(15.0 / 240.0, Player::One);
+        assert_eq!(e.frame, 15);
+        assert_eq!((e.x, e.y), (5.0, 20.0));
+        assert!(e.down && !e.player2);
+
+        // past the last extended action of the player
+        let e = replay.sample_extended_at(30.0 / 240.0, Player::One);
+        assert_eq!(e.frame, 30);
+        assert_eq!((e.x, e.y), (10.0, 30.0));
+    }
+
+    #[test]
+    fn test_spam_regions() {
+        // 8 clicks 0.1 s apart, a pause, then 3 more clicks
+        let times = (0..8)
+            .map(|i| 10.0 + i as f64 * 0.1)
+            .chain([20.0, 20.1, 20.2]);
+        let replay = Replay {
+            actions: times
+                .map(|time| {
+                    Action::new(time, Player::One, Click::Regular(ClickType::Click), 0.0, 0)
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let regions = replay.spam_regions(8);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(*regions[0].start(), 10.0);
+        assert!((regions[0].end() - 10.7).abs() < 1e-9);
+        assert_eq!(replay.spam_regions(3).len(), 2);
+    }
+
+    #[test]
+    fn test_export_as_obs_chapter_markers() {
+        let replay = Replay {
+            fps: 240.0,
+            actions: (0..8)
+                .map(|i| {
+                    let time = 75.0 + i as f64 * 0.1;
+                    Action::new(time, Player::One, Click::Regular(ClickType::Click), 0.0, 0)
+                })
+                .collect(),
+            extended: vec![
+                ExtendedAction {
+                    frame: 240,
+                    x: 500.0,
+                    ..Default::default()
+                },
+                ExtendedAction {
+                    frame: 240 * 65,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_obs_chapter_markers(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "00:00:00 Start\n00:01:05 Death 1\n00:01:15 Spam 1\n"
+        );
+    }
+
+    #[test]
+    fn test_export_smf0_midi_fractional_fps() {
+        let replay = Replay {
+            actions: vec![action(240, Player::One, Click::Regular(ClickType::Click))],
+            ..Default::default()
+        };
+        let mut midi = vec![];
+        replay.export_smf0_midi(&mut midi, 59.94, 60).unwrap();
+        // PPQN in the header, then the delta time of the first note after the tempo event
+        assert_eq!(&midi[12..14], 60u16.to_be_bytes());
+        assert_eq!(&midi[29..33], [60, 0x90, 60, 100]);
+    }
+
+    #[test]
+    fn test_jitter_analysis_fps_change() {
+        let click = Click::Regular(ClickType::Click);
+        let replay = Replay {
+            fps: 60.0,
+            actions: vec![
+                Action::new(0.5, Player::One, click, 0.0, 30),
+                Action::new(1.0, Player::One, click, 0.0, 120),
+            ],
+            extended: vec![ExtendedAction {
+                frame: 60,
+                fps_change: Some(120.0),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let stats = replay.jitter_analysis();
+        assert_eq!(stats.max_jitter_frames, 0.0);
+        assert_eq!(stats.jitter_histogram[0], 2);
+    }
+
+    #[test]
+    fn test_split_by_death_fps_change() {
+        let click = Click::Regular(ClickType::Click);
+        let extended = |frame: u32, x: f32, fps_change| ExtendedAction {
+            frame,
+            x,
+            fps_change,
+            ..Default::default()
+        };
+        // 60 FPS until frame 60 (1 s), then 240 FPS, with a restart at frame 480 (2 s)
+        let replay = Replay {
+            fps: 60.0,
+            duration: 3.0,
+            actions: vec![
+                Action::new(0.5, Player::One, click, 0.0, 30),
+                Action::new(1.5, Player::One, click, 0.0, 360),
+                Action::new(2.5, Player::One, click, 0.0, 600),
+            ],
+            extended: vec![extended(60, 500.0, Some(240.0)), extended(480, 0.0, None)],
+            ..Default::default()
+        };
+        let attempts = replay.split_by_death();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].duration, 2.0);
+        assert_eq!(attempts[0].actions[1].time, 1.5);
+        assert_eq!(attempts[1].duration, 1.0);
+        assert_eq!(attempts[1].actions[0].time, 0.5);
+        assert_eq!(attempts[1].actions[0].frame, 120);
+    }
+
+    #[test]
+    fn test_split_by_death_unknown_fps() {
+        let click = Click::Regular(ClickType::Click);
+        let replay = Replay {
+            fps: 0.0,
+            duration: 3.0,
+            actions: vec![
+                Action::new(0.5, Player::One, click, 0.0, 30),
+                Action::new(2.25, Player::One, click, 0.0, 500),
+                Action::new(2.75, Player::One, click, 0.0, 600),
+            ],
+            extended: vec![
+                ExtendedAction {
+                    frame: 30,
+                    x: 500.0,
+                    ..Default::default()
+                },
+                ExtendedAction {
+                    frame: 480,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let attempts = replay.split_by_death();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].actions[0].time, 0.5);
+        assert_eq!(attempts[0].duration, 0.5);
+        let times: Vec<f64> = attempts[1].actions.iter().map(|a| a.time).collect();
+        assert_eq!(times, [0.0, 0.5]);
+        assert_eq!(attempts[1].duration, 0.75);
+    }
+
+    #[test]
+    fn test_export_as_ogg_chapters_fps_change() {
+        // 60 FPS until frame 60 (1 s), then 240 FPS, with a death at frame 480 (2 s)
+        let replay = Replay {
+            fps: 60.0,
+            duration: 3.0,
+            extended: vec![
+                ExtendedAction {
+                    frame: 60,
+                    x: 500.0,
+                    fps_change: Some(240.0),
+                    ..Default::default()
+                },
+                ExtendedAction {
+                    frame: 480,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_ogg_chapters(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "CHAPTER001=00:00:00.000\nCHAPTER001NAME=Start\n\
+             CHAPTER002=00:00:01.000\nCHAPTER002NAME=FPS change to 240\n\
+             CHAPTER003=00:00:02.000\nCHAPTER003NAME=Death 1\n"
+        );
+    }
+
+    #[test]
+    fn test_export_as_obs_chapter_markers_fps_change() {
+        // 60 FPS until frame 60 (1 s), then 240 FPS, with a death at frame 1200 (5 s)
+        let replay = Replay {
+            fps: 60.0,
+            duration: 20.0,
+            actions: (0..8)
+                .map(|i| {
+                    let time = 10.0 + i as f64 * 0.1;
+                    let frame = (time * 240.0) as u32;
+                    Action::new(
+                        time,
+                        Player::One,
+                        Click::Regular(ClickType::Click),
+                        0.0,
+                        frame,
+                    )
+                })
+                .collect(),
+            extended: vec![
+                ExtendedAction {
+                    frame: 60,
+                    x: 500.0,
+                    fps_change: Some(240.0),
+                    ..Default::default()
+                },
+                ExtendedAction {
+                    frame: 1200,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut out = vec![];
+        replay.export_as_obs_chapter_markers(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "00:00:00 Start\n00:00:05 Death 1\n00:00:10 Spam 1\n"
+        );
+    }
+}
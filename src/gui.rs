@@ -1,8 +1,10 @@
 use crate::built_info;
 use anyhow::{Context, Result};
 use bot::{
-    Action, Bot, ChangeVolumeFor, ClickpackConversionSettings, ExprVariable, ExtendedAction, Pitch,
-    RemoveSilenceFrom, Replay, ReplayType, Timings, VolumeSettings,
+    Action, AudioFormat, AudioSegment, Bot, ChangeVolumeFor, ClassificationMode, ClickType,
+    ClickpackConversionSettings, DryRunStats, ExprVariable, ExtendedAction, GameEvent, JitterStats,
+    Pitch, Player, RemoveSilenceFrom, Replay, ReplayType, Timings, VolumeSettings,
+    SIMULTANEOUS_TOLERANCE_FRAMES,
 };
 use eframe::{
     egui::{self, DragValue, IconData, Key, RichText},
@@ -17,18 +19,63 @@ use rfd::FileDialog;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
-    cell::RefCell,
+    borrow::Cow,
+    cell::{Cell, OnceCell, RefCell},
+    collections::BTreeMap,
     fs::File,
     io::{BufWriter, Cursor, Write},
     ops::RangeInclusive,
     path::Path,
     rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
     time::{Duration, Instant},
 };
 use std::{io::BufReader, path::PathBuf};
 
 const MAX_PLOT_POINTS: usize = 4096;
 
+/// Loads the `base` config, layers the `overlay` config on top of it (see
+/// [`Config::merge`]) and applies the resulting render settings to `args`. Without a
+/// `base`, the overlay is layered on top of the settings passed as flags.
+pub(crate) fn apply_layered_config(
+    args: &mut crate::Args,
+    base: Option<&PathBuf>,
+    overlay: Option<&PathBuf>,
+) -> Result<()> {
+    let mut conf = Config::from_args(args);
+    if let Some(base) = base {
+        conf.load(base).context("failed to load base config")?;
+    }
+    if let Some(overlay) = overlay {
+        let f = std::fs::File::open(overlay).context("failed to open overlay config")?;
+        let overlay: Value = serde_json::from_reader(f).context("failed to load overlay config")?;
+        conf = Config::merge(&conf, overlay).context("failed to merge overlay config")?;
+    }
+    conf.apply_to_args(args);
+    Ok(())
+}
+
+/// Recursively copies the keys of the `overlay` JSON object into `base`.
+fn merge_json(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_json(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
 pub fn run_gui() -> Result<(), eframe::Error> {
     let img = ImageReader::new(Cursor::new(include_bytes!("assets/icon.ico")))
         .with_guessed_format()
@@ -51,11 +98,18 @@ pub fn run_gui() -> Result<(), eframe::Error> {
         options,
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
-            cc.egui_ctx.style_mut(|s| {
+            cc.egui_ctx.all_styles_mut(|s| {
                 s.interaction.tooltip_delay = 0.0;
                 s.url_in_tooltip = true;
             });
-            Ok(Box::<App>::default())
+            let mut app = App::default();
+            if let Some(path) = saved_config_path().filter(|p| p.exists()) {
+                if let Err(e) = app.conf.load(&path) {
+                    log::error!("failed to load saved config from {path:?}: {e}");
+                }
+            }
+            cc.egui_ctx.set_theme(app.conf.theme);
+            Ok(Box::new(app))
         }),
     )
 }
@@ -89,6 +143,68 @@ fn f32_one() -> f32 {
     1.0
 }
 
+fn default_ogg_quality() -> f32 {
+    0.5
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+enum AppTheme {
+    Dark,
+    Light,
+    #[default]
+    System,
+}
+
+impl AppTheme {
+    const fn next(self) -> Self {
+        match self {
+            Self::Dark => Self::Light,
+            Self::Light => Self::System,
+            Self::System => Self::Dark,
+        }
+    }
+
+    const fn icon(self) -> &'static str {
+        match self {
+            Self::Dark => "🌙",
+            Self::Light => "☀",
+            Self::System => "💻",
+        }
+    }
+}
+
+/// How the points of the expression plot are split into separate lines.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+enum PlotGrouping {
+    #[default]
+    All,
+    ByPlayer,
+    ByClickType,
+    /// Groups actions into buckets of this many seconds.
+    ByTimeBucket(f64),
+}
+
+impl std::fmt::Display for PlotGrouping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "All"),
+            Self::ByPlayer => write!(f, "By player"),
+            Self::ByClickType => write!(f, "By click type"),
+            Self::ByTimeBucket(_) => write!(f, "By time bucket"),
+        }
+    }
+}
+
+impl From<AppTheme> for egui::ThemePreference {
+    fn from(theme: AppTheme) -> Self {
+        match theme {
+            AppTheme::Dark => Self::Dark,
+            AppTheme::Light => Self::Light,
+            AppTheme::System => Self::System,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct Config {
     #[serde(default = "get_version")]
@@ -112,6 +228,100 @@ struct Config {
     cut_sounds: bool,
     #[serde(default = "f32_one")]
     noise_volume: f32,
+    #[serde(default = "AppTheme::default")]
+    theme: AppTheme,
+    #[serde(default = "bool::default")]
+    invert_inputs: bool,
+    #[serde(default = "ClassificationMode::default")]
+    classification_mode: ClassificationMode,
+    #[serde(default = "bool::default")]
+    remove_releases: bool,
+    #[serde(default = "bool::default")]
+    remove_clicks: bool,
+    #[serde(default = "PlotGrouping::default")]
+    plot_grouping: PlotGrouping,
+    #[serde(default = "bool::default")]
+    midi_smf0: bool,
+    #[serde(default = "bool::default")]
+    remove_redundant_holds: bool,
+    #[serde(default = "f32::default")]
+    humanise_strength: f32,
+    #[serde(default = "f32_one")]
+    sidechain_ratio: f32,
+    #[serde(default = "AudioFormat::default")]
+    output_format: AudioFormat,
+    #[serde(default = "default_ogg_quality")]
+    ogg_quality: f32,
+}
+
+/// A setting that differs between two configs, see [`Config::diff`].
+struct ConfigDiff {
+    field: &'static str,
+    old_value: String,
+    new_value: String,
+}
+
+impl std::fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({} → {})",
+            self.field, self.old_value, self.new_value
+        )
+    }
+}
+
+/// Invokes `$callback!` with the paths of all settings in [`Config`], so that
+/// per-setting operations don't have to repeat the list.
+macro_rules! for_each_config_field {
+    ($callback:ident) => {
+        $callback!(
+            version,
+            noise,
+            normalize,
+            pitch_enabled,
+            pitch,
+            timings.hard,
+            timings.regular,
+            timings.soft,
+            vol_settings.enabled,
+            vol_settings.spam_time,
+            vol_settings.spam_vol_offset_factor,
+            vol_settings.max_spam_vol_offset,
+            vol_settings.change_releases_volume,
+            vol_settings.global_volume,
+            vol_settings.volume_var,
+            vol_settings.simultaneous_click_boost,
+            vol_settings.stereo_separation,
+            litematic_export_releases,
+            midi_key,
+            midi_smf0,
+            sample_rate,
+            expr_text,
+            expr_variable,
+            sort_actions,
+            plot_data_aspect,
+            conversion_settings,
+            cut_sounds,
+            noise_volume,
+            theme,
+            invert_inputs,
+            classification_mode,
+            remove_releases,
+            remove_clicks,
+            remove_redundant_holds,
+            plot_grouping,
+            humanise_strength,
+            sidechain_ratio,
+            output_format,
+            ogg_quality,
+        )
+    };
+}
+
+/// Path of the config that is loaded on startup and saved when the theme changes.
+fn saved_config_path() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "zcb").map(|dirs| dirs.config_dir().join("config.json"))
 }
 
 impl Config {
@@ -121,16 +331,165 @@ impl Config {
         Ok(())
     }
 
+    /// Saves the config to [`saved_config_path`], which is loaded on startup.
+    fn save_to_config_dir(&self) -> Result<()> {
+        let path = saved_config_path().context("no config directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        self.save(&path)
+    }
+
     fn load(&mut self, path: &PathBuf) -> Result<()> {
         let f = std::fs::File::open(path)?;
         *self = serde_json::from_reader(f)?;
         Ok(())
     }
 
+    /// Returns the settings that are different in `other`.
+    fn diff(&self, other: &Self) -> Vec<ConfigDiff> {
+        let mut diffs = vec![];
+        macro_rules! diff_fields {
+            ($($($field:ident).+),* $(,)?) => {$(
+                let (old_value, new_value) =
+                    (format!("{:?}", self.$($field).+), format!("{:?}", other.$($field).+));
+                if old_value != new_value {
+                    diffs.push(ConfigDiff {
+                        field: stringify!($($field).+),
+                        old_value,
+                        new_value,
+                    });
+                }
+            )*};
+        }
+        for_each_config_field!(diff_fields);
+        diffs
+    }
+
+    /// Layers the `overlay` config (as JSON) on top of `base`. Only the settings that are in
+    /// `overlay` override the ones in `base`, so it can be a partial config file.
+    fn merge(base: &Config, mut overlay: Value) -> Result<Config> {
+        if let Value::Object(overlay) = &mut overlay {
+            overlay.remove("version");
+        }
+        let mut merged = serde_json::to_value(base)?;
+        merge_json(&mut merged, overlay);
+        Ok(serde_json::from_value(merged)?)
+    }
+
+    /// Render settings of the command line arguments, the inverse of
+    /// [`Config::apply_to_args`]. Settings without a flag keep their defaults.
+    fn from_args(args: &crate::Args) -> Self {
+        Self {
+            noise: args.noise,
+            noise_volume: args.noise_volume,
+            normalize: args.normalize,
+            pitch_enabled: args.pitch_enabled,
+            pitch: Pitch {
+                from: args.pitch_from,
+                to: args.pitch_to,
+                step: args.pitch_step,
+            },
+            timings: Timings {
+                hard: args.hard_timing,
+                regular: args.regular_timing,
+                soft: args.soft_timing,
+            },
+            vol_settings: VolumeSettings {
+                enabled: args.vol_enabled,
+                spam_time: args.spam_time,
+                spam_vol_offset_factor: args.spam_vol_offset_factor,
+                max_spam_vol_offset: args.max_spam_vol_offset,
+                change_releases_volume: args.change_releases_volume,
+                global_volume: args.global_volume,
+                volume_var: args.volume_var,
+                simultaneous_click_boost: args.simultaneous_click_boost,
+                stereo_separation: args.stereo_separation,
+            },
+            sample_rate: args.sample_rate,
+            sort_actions: args.sort_actions,
+            expr_text: args.volume_expr.clone(),
+            expr_variable: match args.expr_variable {
+                crate::ArgExprVariable::None => ExprVariable::None,
+                crate::ArgExprVariable::Value => ExprVariable::Value,
+                crate::ArgExprVariable::TimeOffset => ExprVariable::TimeOffset,
+                crate::ArgExprVariable::Variation => ExprVariable::Variation {
+                    negative: args.expr_negative,
+                },
+            },
+            cut_sounds: args.cut_sounds,
+            humanise_strength: args.humanise_strength,
+            sidechain_ratio: args.sidechain_ratio,
+            // the same format the CLI would pick for the output path
+            output_format: match args.output_format {
+                Some(crate::OutputFormat::Wav) => AudioFormat::Wav,
+                Some(crate::OutputFormat::Flac) => AudioFormat::Flac,
+                Some(crate::OutputFormat::Ogg) => AudioFormat::Ogg,
+                None => AudioFormat::from_path(Path::new(&args.output)).unwrap_or_default(),
+            },
+            ogg_quality: args.ogg_quality,
+            ..Default::default()
+        }
+    }
+
+    /// Applies the render settings to the command line arguments.
+    fn apply_to_args(&self, args: &mut crate::Args) {
+        args.noise = self.noise;
+        args.noise_volume = self.noise_volume;
+        args.normalize = self.normalize;
+        args.pitch_enabled = self.pitch_enabled;
+        args.pitch_from = self.pitch.from;
+        args.pitch_to = self.pitch.to;
+        args.pitch_step = self.pitch.step;
+        args.hard_timing = self.timings.hard;
+        args.regular_timing = self.timings.regular;
+        args.soft_timing = self.timings.soft;
+        args.vol_enabled = self.vol_settings.enabled;
+        args.spam_time = self.vol_settings.spam_time;
+        args.spam_vol_offset_factor = self.vol_settings.spam_vol_offset_factor;
+        args.max_spam_vol_offset = self.vol_settings.max_spam_vol_offset;
+        args.change_releases_volume = self.vol_settings.change_releases_volume;
+        args.global_volume = self.vol_settings.global_volume;
+        args.volume_var = self.vol_settings.volume_var;
+        args.simultaneous_click_boost = self.vol_settings.simultaneous_click_boost;
+        args.stereo_separation = self.vol_settings.stereo_separation;
+        args.sample_rate = self.sample_rate;
+        args.sort_actions = self.sort_actions;
+        args.volume_expr = self.expr_text.clone();
+        args.expr_variable = match self.expr_variable {
+            ExprVariable::None => crate::ArgExprVariable::None,
+            ExprVariable::Value => crate::ArgExprVariable::Value,
+            ExprVariable::TimeOffset => crate::ArgExprVariable::TimeOffset,
+            ExprVariable::Variation { negative } => {
+                args.expr_negative = negative;
+                crate::ArgExprVariable::Variation
+            }
+        };
+        args.cut_sounds = self.cut_sounds;
+        args.humanise_strength = self.humanise_strength;
+        args.sidechain_ratio = self.sidechain_ratio;
+        args.output_format = Some(match self.output_format {
+            AudioFormat::Wav => crate::OutputFormat::Wav,
+            AudioFormat::Flac => crate::OutputFormat::Flac,
+            AudioFormat::Ogg => crate::OutputFormat::Ogg,
+        });
+        args.ogg_quality = self.ogg_quality;
+    }
+
     fn replay_changed(&self, other: &Self) -> bool {
+        // stereo separation is applied when rendering, see `Replay::vol_settings`
+        let parse_vol_settings = |conf: &Self| VolumeSettings {
+            stereo_separation: 0.0,
+            ..conf.vol_settings
+        };
         self.timings != other.timings
-            || self.vol_settings != other.vol_settings
+            || parse_vol_settings(self) != parse_vol_settings(other)
             || self.sort_actions != other.sort_actions
+            || self.invert_inputs != other.invert_inputs
+            || self.classification_mode != other.classification_mode
+            || self.remove_releases != other.remove_releases
+            || self.remove_clicks != other.remove_clicks
+            || self.remove_redundant_holds != other.remove_redundant_holds
     }
 }
 
@@ -154,6 +513,76 @@ impl Default for Config {
             conversion_settings: ClickpackConversionSettings::default(),
             cut_sounds: false,
             noise_volume: 1.0,
+            theme: AppTheme::default(),
+            invert_inputs: false,
+            classification_mode: ClassificationMode::default(),
+            remove_releases: false,
+            remove_clicks: false,
+            plot_grouping: PlotGrouping::default(),
+            midi_smf0: false,
+            remove_redundant_holds: false,
+            humanise_strength: 0.0,
+            sidechain_ratio: 1.0,
+            output_format: AudioFormat::default(),
+            ogg_quality: default_ogg_quality(),
+        }
+    }
+}
+
+/// Progress of a background render, shared with the render thread.
+struct RenderProgress {
+    /// Number of rendered actions.
+    completed: usize,
+    total: usize,
+    /// Set once the render thread is done.
+    result: Option<Result<()>>,
+}
+
+/// A render running on a background thread, see [`App::spawn_render`].
+struct RenderTask {
+    progress: Arc<Mutex<RenderProgress>>,
+    cancel: Arc<AtomicBool>,
+    /// Returns the bot, which is moved to the render thread.
+    handle: JoinHandle<Bot>,
+    start: Instant,
+    /// Where the output is written, `None` if the render is played as a preview.
+    output: Option<PathBuf>,
+}
+
+/// Statistics of a replay that are too slow to compute every frame.
+struct ReplayStats {
+    attempts: Vec<Replay>,
+    num_restarts: usize,
+    num_simultaneous: usize,
+    entropy: f64,
+    reaction_times: Vec<f64>,
+    jitter: JitterStats,
+    fps_variance: f64,
+    fps_range: (f64, f64),
+    average_bpm: f64,
+    median_bpm: f64,
+    x_range: RangeInclusive<f32>,
+}
+
+impl ReplayStats {
+    fn new(replay: &Replay) -> Self {
+        let num_simultaneous = replay
+            .group_simultaneous_actions(SIMULTANEOUS_TOLERANCE_FRAMES)
+            .iter()
+            .filter(|group| group.iter().filter(|a| a.click.is_click()).count() >= 2)
+            .count();
+        Self {
+            attempts: replay.split_by_death(),
+            num_restarts: replay.detect_level_restarts().len(),
+            num_simultaneous,
+            entropy: replay.compute_entropy(),
+            reaction_times: replay.compute_reaction_time_distribution(),
+            jitter: replay.jitter_analysis(),
+            fps_variance: replay.fps_variance(),
+            fps_range: replay.fps_range(),
+            average_bpm: replay.average_action_rate_bpm(),
+            median_bpm: replay.median_action_rate_bpm(),
+            x_range: replay.compute_x_range(),
         }
     }
 }
@@ -175,13 +604,56 @@ struct App {
     conf_after_replay_selected: Option<Config>,
     replay_path: Option<PathBuf>,
     clickpack_num_sounds: Option<usize>,
+    /// How long the last clickpack reload took.
+    clickpack_reload_time: Option<Duration>,
     clickpack_has_noise: bool,
+    num_clipping_files: usize,
+    clipping_filenames: Vec<String>,
     expr_variable_variation_negative: bool,
     override_fps_enabled: bool,
     override_fps: f64,
+    /// Whether renders are seeded with `seed`, see [`Bot::with_seed`].
+    seed_enabled: bool,
+    seed: u64,
+    remap_buttons: (i32, i32),
+    replay_diff: Option<Replay>,
+    /// Whether the loaded replay was changed after it was parsed.
+    replay_modified: bool,
+    osu_circle_size: f32,
+    stepmania_bpm: f64,
+    /// Whether to only keep every `subsample_every`-th action in the render preview.
+    subsample_preview: bool,
+    subsample_every: usize,
+    /// The replay subsampled for the preview, with the `subsample_every` it was made with.
+    subsampled_replay: Option<(usize, Replay)>,
+    /// Render preview statistics, with the `subsample_every` they were computed with
+    /// (`None` for the whole replay). Cleared when the replay or the clickpack changes.
+    dry_run_stats: Cell<Option<(Option<usize>, DryRunStats)>>,
+    /// Whether physics exports in the statistics panel use mirrored X positions.
+    mirror_x: bool,
+    /// Text of the "Load from base64 string" field.
+    base64_replay: String,
+    /// Attempt (see [`Replay::split_by_death`]) to render instead of the whole replay.
+    selected_attempt: Option<usize>,
+    /// Statistics of the current replay, computed the first time they are needed.
+    replay_stats: OnceCell<ReplayStats>,
+    /// Game events from "Load game events", attached to every loaded replay.
+    game_events: Vec<GameEvent>,
+    /// Whether multiple replays are rendered into one output.
+    composite_enabled: bool,
+    /// Audio of all replays added to the composite so far.
+    composite: Option<AudioSegment>,
+    composite_num_replays: usize,
+    /// Where the next replay is placed in the composite, in seconds.
+    composite_offset: f64,
+    /// Lua script that overrides the click type, volume and pitch of every action.
+    #[cfg(feature = "lua")]
+    script: String,
     clickpack_db: ClickpackDb,
     show_clickpack_db: bool,
     clickpack_db_title: String,
+    /// The render that is in progress, if any.
+    render_task: Option<RenderTask>,
 }
 
 impl Default for App {
@@ -203,13 +675,39 @@ impl Default for App {
             conf_after_replay_selected: None,
             replay_path: None,
             clickpack_num_sounds: None,
+            clickpack_reload_time: None,
             clickpack_has_noise: false,
+            num_clipping_files: 0,
+            clipping_filenames: vec![],
             expr_variable_variation_negative: true,
             override_fps_enabled: false,
             override_fps: 0.0,
+            seed_enabled: false,
+            seed: 0,
+            remap_buttons: (1, 1),
+            replay_diff: None,
+            replay_modified: false,
+            osu_circle_size: 4.0,
+            stepmania_bpm: 120.0,
+            subsample_preview: false,
+            subsample_every: 10,
+            subsampled_replay: None,
+            dry_run_stats: Cell::new(None),
+            mirror_x: false,
+            base64_replay: String::new(),
+            selected_attempt: None,
+            replay_stats: OnceCell::new(),
+            game_events: vec![],
+            composite_enabled: false,
+            composite: None,
+            composite_num_replays: 0,
+            composite_offset: 0.0,
+            #[cfg(feature = "lua")]
+            script: String::new(),
             clickpack_db: ClickpackDb::default(),
             show_clickpack_db: false,
             clickpack_db_title: String::new(),
+            render_task: None,
         }
     }
 }
@@ -235,6 +733,18 @@ fn help_text<R>(ui: &mut egui::Ui, help: &str, add_contents: impl FnOnce(&mut eg
     });
 }
 
+/// Names of the click type directories, in [`bot::PlayerClicks`] index order.
+const CLICK_TYPE_DIRNAMES: [&str; 8] = [
+    "hardclicks",
+    "hardreleases",
+    "clicks",
+    "releases",
+    "softclicks",
+    "softreleases",
+    "microclicks",
+    "microreleases",
+];
+
 fn drag_value<Num: emath::Numeric>(
     ui: &mut egui::Ui,
     value: &mut Num,
@@ -256,8 +766,57 @@ fn drag_value<Num: emath::Numeric>(
     });
 }
 
+/// Writes `segment` to `output` in the output format of `conf`.
+fn write_segment(conf: &Config, output: &Path, segment: &AudioSegment) -> Result<()> {
+    let f = File::create(output).context("failed to open output file")?;
+    match conf.output_format {
+        AudioFormat::Wav => segment.export_wav(f),
+        AudioFormat::Flac => segment.export_flac(f),
+        AudioFormat::Ogg => segment.export_ogg(f, conf.ogg_quality),
+    }
+}
+
+/// Renders `replay` with the render settings of `conf`. Runs on the render thread, see
+/// [`App::spawn_render`].
+fn render_segment(
+    bot: &mut Bot,
+    replay: &Replay,
+    conf: &Config,
+    expr_var: ExprVariable,
+    cancel: &AtomicBool,
+    on_progress: impl FnMut(usize),
+) -> Result<AudioSegment> {
+    let segment = if conf.noise && conf.sidechain_ratio < 1.0 {
+        bot.render_with_sidechain_compression(
+            replay,
+            conf.noise_volume,
+            conf.sidechain_ratio,
+            conf.normalize,
+            expr_var,
+            conf.pitch_enabled,
+            conf.cut_sounds,
+        )?
+    } else {
+        bot.render_replay_cancellable(
+            replay,
+            conf.noise,
+            conf.noise_volume,
+            conf.normalize,
+            expr_var,
+            conf.pitch_enabled,
+            conf.cut_sounds,
+            cancel,
+            on_progress,
+        )
+        .context("rendering was cancelled")?
+    };
+    Ok(segment)
+}
+
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        ctx.set_theme(self.conf.theme);
+
         ctx.input(|i| {
             use Key::*;
             const BOYKISSER: [Key; 9] = [B, O, Y, K, I, S, S, E, R];
@@ -319,6 +878,16 @@ impl eframe::App for App {
 
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.style_mut().spacing.item_spacing.x = 5.;
+                        if ui
+                            .button(self.conf.theme.icon())
+                            .on_hover_text(format!("Theme: {:?}", self.conf.theme))
+                            .clicked()
+                        {
+                            self.conf.theme = self.conf.theme.next();
+                            if let Err(e) = self.conf.save_to_config_dir() {
+                                log::error!("failed to save config: {e}");
+                            }
+                        }
                         if ui
                             .button("Reset")
                             .on_hover_text("Reset the current configuration to defaults")
@@ -360,31 +929,45 @@ impl eframe::App for App {
             self.show_update_check_modal(&modal, &update_dialog, ctx);
         });
 
+        if self.render_task.is_some() {
+            egui::TopBottomPanel::bottom("render_progress_panel").show(ctx, |ui| {
+                ui.add_space(2.0);
+                self.show_render_progress(ui);
+                ui.add_space(2.0);
+            });
+        }
+
+        // the bot belongs to the render thread until it's done, so everything that could
+        // use it is disabled
+        let is_rendering = self.render_task.is_some();
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::both().show(ui, |ui| {
-                match self.stage {
+                ui.add_enabled_ui(!is_rendering, |ui| match self.stage {
                     Stage::SelectReplay => self.show_replay_stage(ctx, ui),
                     Stage::SelectClickpack => self.show_select_clickpack_stage(ctx, ui),
                     Stage::Render => self.show_render_stage(ctx, ui),
                     // Stage::AutoCutter => self.autocutter.show_ui(ctx, ui),
                     Stage::Donate => self.show_pwease_donate_stage(ctx, ui),
                     Stage::Secret => self.show_secret_stage(ctx, ui),
-                };
+                });
             });
         });
 
+        let mut render_dialog = Modal::new(ctx, "render_dialog");
+        self.poll_render_task(ctx, &render_dialog);
+        render_dialog.show_dialog();
+
+        let mut drop_dialog = Modal::new(ctx, "drop_dialog");
+        self.handle_dropped_files(ctx, &drop_dialog);
+        drop_dialog.show_dialog();
+
         if self.show_clickpack_db {
             if self.clickpack_db_title.is_empty() {
                 let updated_at = self.clickpack_db.db.read().unwrap().updated_at_unix;
                 if updated_at != 0 {
-                    use chrono::{TimeZone, Utc};
-                    use timeago::Formatter;
-                    let formatter = Formatter::new();
-                    let datetime = Utc.timestamp_opt(updated_at, 0).unwrap();
-                    let now = Utc::now();
                     self.clickpack_db_title = format!(
                         "ClickpackDB - updated {}, {} clickpacks",
-                        formatter.convert_chrono(datetime, now),
+                        format_time_ago(updated_at),
                         self.clickpack_db.db.read().unwrap().entries.len()
                     );
                 }
@@ -407,7 +990,7 @@ impl eframe::App for App {
                     );
 
                     egui::CentralPanel::default().show(ctx, |ui| {
-                        self.show_clickpack_db(ctx, ui);
+                        ui.add_enabled_ui(!is_rendering, |ui| self.show_clickpack_db(ctx, ui));
                     });
 
                     if ctx.input(|i| i.viewport().close_requested()) {
@@ -442,6 +1025,78 @@ fn ureq_get(url: &str) -> Result<Vec<u8>, String> {
     Ok(buf)
 }
 
+/// Name of the cached clickpack database file. Requests to URLs ending with this are cached.
+const DB_CACHE_FILENAME: &str = "db.json";
+/// Name of the file storing the unix timestamp of the cached clickpack database.
+const DB_CACHE_TIMESTAMP_FILENAME: &str = "db.timestamp";
+/// Unix timestamp of the cached database if it's currently in use, 0 otherwise.
+static CACHED_DB_TIMESTAMP: AtomicI64 = AtomicI64::new(0);
+
+fn db_cache_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "zcb").map(|dirs| dirs.cache_dir().to_path_buf())
+}
+
+fn write_db_cache(body: &[u8]) -> Result<()> {
+    let dir = db_cache_dir().context("no cache directory")?;
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join(DB_CACHE_FILENAME), body)?;
+    std::fs::write(
+        dir.join(DB_CACHE_TIMESTAMP_FILENAME),
+        chrono::Utc::now().timestamp().to_string(),
+    )?;
+    Ok(())
+}
+
+fn read_db_cache() -> Result<(Vec<u8>, i64)> {
+    let dir = db_cache_dir().context("no cache directory")?;
+    let body = std::fs::read(dir.join(DB_CACHE_FILENAME))?;
+    let timestamp = std::fs::read_to_string(dir.join(DB_CACHE_TIMESTAMP_FILENAME))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0);
+    Ok((body, timestamp))
+}
+
+/// Formats a unix timestamp relative to now, e.g. "3 days ago".
+fn format_time_ago(timestamp: i64) -> String {
+    use chrono::{TimeZone, Utc};
+    match Utc.timestamp_opt(timestamp, 0).single() {
+        Some(datetime) => timeago::Formatter::new().convert_chrono(datetime, Utc::now()),
+        None => "at an unknown time".to_string(),
+    }
+}
+
+fn clear_db_cache() -> Result<()> {
+    let dir = db_cache_dir().context("no cache directory")?;
+    if dir.try_exists()? {
+        std::fs::remove_dir_all(dir)?;
+    }
+    CACHED_DB_TIMESTAMP.store(0, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Same as [`ureq_get`], but caches the clickpack database so it can be used offline.
+fn ureq_get_cached(url: &str) -> Result<Vec<u8>, String> {
+    if !url.ends_with(DB_CACHE_FILENAME) {
+        return ureq_get(url);
+    }
+    match ureq_get(url) {
+        Ok(body) => {
+            if let Err(e) = write_db_cache(&body) {
+                log::error!("failed to cache clickpack database: {e}");
+            }
+            CACHED_DB_TIMESTAMP.store(0, Ordering::Relaxed);
+            Ok(body)
+        }
+        Err(e) => {
+            log::warn!("failed to download clickpack database ({e}), trying cache");
+            let (body, timestamp) = read_db_cache().map_err(|_| e)?;
+            CACHED_DB_TIMESTAMP.store(timestamp.max(1), Ordering::Relaxed);
+            Ok(body)
+        }
+    }
+}
+
 fn get_latest_tag() -> Result<String> {
     let body = ureq_agent()
         .get("https://api.github.com/repos/zeozeozeo/zcb3/tags")
@@ -720,6 +1375,14 @@ impl App {
             return Err(anyhow::anyhow!("no file was selected"));
         };
 
+        if self.conf.midi_smf0 {
+            let mut writer = BufWriter::new(File::create(path)?);
+            self.replay
+                .export_smf0_midi(&mut writer, self.replay.fps, self.conf.midi_key)?;
+            writer.flush()?;
+            return Ok(());
+        }
+
         // Separate the click types into their own vectors
         let mut separated_actions: [Vec<Action>; 8] = Default::default();
         for action in &self.replay.actions {
@@ -892,6 +1555,7 @@ impl App {
             .add_filter("Config file", &["json"])
             .pick_file()
         {
+            let prev_conf = self.conf.clone();
             if let Err(e) = self.conf.load(&file) {
                 dialog
                     .dialog()
@@ -901,6 +1565,19 @@ impl App {
                     .open();
             } else {
                 self.update_expr = true;
+                let diffs = prev_conf.diff(&self.conf);
+                let body = if diffs.is_empty() {
+                    "No settings were changed".to_string()
+                } else {
+                    let diffs: Vec<String> = diffs.iter().map(ToString::to_string).collect();
+                    format!("Changed: {}", diffs.join(", "))
+                };
+                dialog
+                    .dialog()
+                    .with_title("Loaded config")
+                    .with_body(body)
+                    .with_icon(Icon::Info)
+                    .open();
             }
         } else {
             dialog
@@ -955,6 +1632,11 @@ impl App {
 
                 ui.label(format!("MIDI key ({note}{octave})"));
             });
+            ui.checkbox(&mut self.conf.midi_smf0, "Single track (SMF type 0)")
+                .on_hover_text(
+                    "Write all events to one track, with the velocity depending on the click \
+                    type and player 2 on a separate MIDI channel",
+                );
         });
 
         #[cfg(windows)]
@@ -1029,32 +1711,99 @@ impl App {
             });
     }
 
+    /// Returns a replay builder with the current settings applied.
+    fn replay_builder(&self) -> Replay {
+        let builder = Replay::build()
+            .with_timings(self.conf.timings)
+            .with_vol_settings(self.conf.vol_settings)
+            .with_extended(true)
+            .with_sort_actions(self.conf.sort_actions)
+            .with_classification_mode(self.conf.classification_mode)
+            .with_override_fps(if self.override_fps_enabled {
+                Some(self.override_fps)
+            } else {
+                None
+            });
+        if self.seed_enabled {
+            builder.with_seed(self.seed)
+        } else {
+            builder
+        }
+    }
+
+    /// Applies the replay filters to a freshly parsed replay and makes it the current one.
+    fn set_loaded_replay(&mut self, replay: Replay) {
+        self.replay = if self.conf.invert_inputs {
+            replay.invert_all_inputs()
+        } else {
+            replay
+        };
+        // has to run before removing releases or clicks, which create runs of the
+        // same state
+        if self.conf.remove_redundant_holds {
+            let count = self.replay.deduplicate_consecutive_same_state();
+            log::info!("removed {count} redundant holds");
+        }
+        if self.conf.remove_releases {
+            let count = self.replay.remove_all_releases();
+            log::info!("removed {count} releases");
+        }
+        if self.conf.remove_clicks {
+            let count = self.replay.remove_all_clicks();
+            log::info!("removed {count} clicks");
+        }
+        if !self.game_events.is_empty() {
+            self.replay = std::mem::take(&mut self.replay)
+                .annotate_with_game_events(self.game_events.clone());
+        }
+        self.replay_diff = None;
+        self.replay_modified = false;
+        self.selected_attempt = None;
+        self.clear_replay_caches();
+        self.update_expr = true;
+        self.conf_after_replay_selected = Some(self.conf.clone());
+    }
+
+    fn load_replay_from_base64(&mut self, dialog: &Modal) {
+        match self
+            .replay_builder()
+            .import_from_base64_string(&self.base64_replay)
+        {
+            Ok(replay) => {
+                self.replay_path = None; // can't be reloaded
+                self.set_loaded_replay(replay);
+                self.base64_replay.clear();
+                self.stage = Stage::SelectClickpack;
+            }
+            Err(e) => {
+                dialog
+                    .dialog()
+                    .with_title("Failed to load replay")
+                    .with_body(capitalize_first_letter(&e.to_string()))
+                    .with_icon(Icon::Error)
+                    .open();
+            }
+        }
+    }
+
     fn load_replay(&mut self, dialog: &Modal, file: &Path) -> Result<()> {
         let filename = file.file_name().unwrap().to_str().unwrap();
 
-        // open replay file
-        let f = std::fs::File::open(file).unwrap();
-
         let replay_type = ReplayType::guess_format(filename);
 
         if let Ok(replay_type) = replay_type {
             // parse replay
-            let replay = Replay::build()
-                .with_timings(self.conf.timings)
-                .with_vol_settings(self.conf.vol_settings)
-                .with_extended(true)
-                .with_sort_actions(self.conf.sort_actions)
-                .with_override_fps(if self.override_fps_enabled {
-                    Some(self.override_fps)
-                } else {
-                    None
-                })
-                .parse(replay_type, BufReader::new(f));
+            #[cfg(feature = "mmap")]
+            let replay = std::fs::File::open(file)
+                .map_err(anyhow::Error::from)
+                .and_then(|f| self.replay_builder().parse_memory_mapped(replay_type, &f));
+            #[cfg(not(feature = "mmap"))]
+            let replay = std::fs::read(file)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| self.replay_builder().parse_bytes(replay_type, &data));
 
             if let Ok(replay) = replay {
-                self.replay = replay;
-                self.update_expr = true;
-                self.conf_after_replay_selected = Some(self.conf.clone());
+                self.set_loaded_replay(replay);
             } else if let Err(e) = replay {
                 dialog
                     .dialog()
@@ -1079,65 +1828,515 @@ impl App {
         Ok(())
     }
 
-    fn show_replay_stage(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.heading("Select replay file");
+    /// Loads another replay and keeps the actions of the current replay that are not in it.
+    fn diff_replay(&mut self, dialog: &Modal, file: &Path) {
+        let filename = file.file_name().unwrap().to_str().unwrap();
+        let other = ReplayType::guess_format(filename).and_then(|typ| {
+            let data = std::fs::read(file)?;
+            self.replay_builder().parse_bytes(typ, &data)
+        });
+        match other {
+            Ok(other) => self.replay_diff = Some(self.replay.subtract(&other)),
+            Err(e) => {
+                dialog
+                    .dialog()
+                    .with_title("Failed to load replay")
+                    .with_body(capitalize_first_letter(&e.to_string()))
+                    .with_icon(Icon::Error)
+                    .open();
+            }
+        }
+    }
 
-        let mut dialog = Modal::new(ctx, "replay_stage_dialog");
+    /// The replay used for physics exports, mirrored if "Mirror X" is enabled.
+    fn physics_replay(&self) -> Cow<'_, Replay> {
+        if self.mirror_x {
+            Cow::Owned(self.replay.mirror_x_positions())
+        } else {
+            Cow::Borrowed(&self.replay)
+        }
+    }
 
-        ui.collapsing("Timings", |ui| {
-            ui.label("Click type timings. The number is the delay between actions (in seconds). \
-                    If the delay between the current and previous action is bigger than the specified \
-                    timing, the corresponding click type is used.");
-            let t = &mut self.conf.timings;
+    /// Writes the physics of both players next to `path`, with `_p1` and `_p2` suffixes.
+    fn export_blender_csv(&self, path: &Path) -> Result<()> {
+        let stem = path
+            .file_stem()
+            .map_or("physics".into(), |s| s.to_string_lossy());
+        for (player, suffix) in [(Player::One, "p1"), (Player::Two, "p2")] {
+            let path = path.with_file_name(format!("{stem}_{suffix}.csv"));
+            log::info!("exporting physics to {path:?}");
+            let mut writer = BufWriter::new(File::create(&path)?);
+            self.physics_replay()
+                .export_as_blender_csv(&mut writer, player)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
 
-            drag_value(ui, &mut t.hard, "Hard timing",
-            t.regular..=f64::INFINITY,
-            "Hardclick/hardrelease timing");
-            drag_value(ui, &mut t.regular, "Regular timing", t.soft..=t.hard,
-            "Click/release timing");
-            drag_value(ui, &mut t.soft, "Soft timing", 0.0..=t.regular,
-            "Softclick/softrelease timing");
-            ui.label(format!("Everything below {}s are microclicks/microreleases.", t.soft));
-        });
+    fn save_heatmap(&self, path: &Path) -> Result<()> {
+        const SIZE: (u32, u32) = (1024, 256);
+        let replay = self.physics_replay();
+        let pixels = replay.compute_click_density_image(SIZE.0, SIZE.1);
+        let mut image = image::RgbaImage::from_raw(SIZE.0, SIZE.1, pixels)
+            .context("heatmap has an invalid size")?;
+
+        // mark restarts with white vertical lines
+        if replay.duration > 0.0 {
+            let restarts = replay.frames_to_times(&replay.detect_level_restarts());
+            for time in restarts.into_iter().flatten() {
+                let col = ((time / replay.duration * SIZE.0 as f64) as u32).min(SIZE.0 - 1);
+                for row in 0..SIZE.1 {
+                    image.put_pixel(col, row, image::Rgba([255, 255, 255, 255]));
+                }
+            }
 
-        ui.collapsing("Volume settings", |ui| {
-            ui.label(
-                "General volume settings. The volume variation variable \
-                defines the range of the random volume offset.",
-            );
+            // mark game events with colored ticks at the top
+            let frames: Vec<u32> = replay.events.iter().map(|e| e.frame).collect();
+            let times = replay.frames_to_times(&frames);
+            for (event, time) in replay.events.iter().zip(times) {
+                let Some(time) = time else {
+                    continue;
+                };
+                let col = ((time / replay.duration * SIZE.0 as f64) as u32).min(SIZE.0 - 1);
+                let [r, g, b] = event.event_type.color();
+                for row in 0..16 {
+                    image.put_pixel(col, row, image::Rgba([r, g, b, 255]));
+                }
+            }
+        }
+        image.save(path)?;
+        Ok(())
+    }
 
-            let vol = &mut self.conf.vol_settings;
-            drag_value(
-                ui,
-                &mut vol.volume_var,
-                "Volume variation",
-                0.0..=f32::INFINITY,
-                "Maximum volume variation for each action (+/-)",
-            );
-            drag_value(
-                ui,
-                &mut vol.global_volume,
-                "Global volume",
-                0.0..=f32::INFINITY,
-                "Constant volume multiplier for all actions",
-            );
-        });
+    fn load_game_events(&mut self, path: &Path) -> Result<()> {
+        let f = std::fs::File::open(path)?;
+        self.game_events =
+            serde_json::from_reader(BufReader::new(f)).context("failed to parse game events")?;
+        self.replay =
+            std::mem::take(&mut self.replay).annotate_with_game_events(self.game_events.clone());
+        self.clear_replay_caches();
+        Ok(())
+    }
 
-        ui.collapsing("Spam volume changes", |ui| {
-            ui.label(
-                "Adjusts the volume of 'spam clicks', which are defined as actions within \
-                a maximum time limit, known as the 'spam time'. The volume offset \
-                is based on the delta time between actions, multiplied by the spam \
-                volume offset factor, and clamped \
-                by the maximum spam volume offset. \
-                In short, this can be used to lower the volume of clicks in spams.",
-            );
+    fn replay_stats(&self) -> &ReplayStats {
+        self.replay_stats
+            .get_or_init(|| ReplayStats::new(&self.replay))
+    }
 
-            let vol = &mut self.conf.vol_settings;
-            ui.checkbox(&mut vol.enabled, "Enable spam volume changes");
+    /// Clears everything that was computed from the current replay.
+    fn clear_replay_caches(&mut self) {
+        self.replay_stats.take();
+        self.subsampled_replay = None;
+        self.dry_run_stats.take();
+    }
 
-            ui.add_enabled_ui(vol.enabled, |ui| {
-                help_text(ui, "Apply spam volume changes to releases", |ui| {
+    /// Lists the attempts of the replay and lets the user pick the one to render.
+    fn show_attempts(&mut self, ui: &mut egui::Ui) {
+        let attempts = &self
+            .replay_stats
+            .get_or_init(|| ReplayStats::new(&self.replay))
+            .attempts;
+        if ui
+            .selectable_label(self.selected_attempt.is_none(), "Render all attempts")
+            .clicked()
+        {
+            self.selected_attempt = None;
+        }
+        egui::Grid::new("replay_attempts_grid")
+            .num_columns(4)
+            .spacing([20.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                for header in ["Attempt", "Duration", "Actions", ""] {
+                    ui.label(header);
+                }
+                ui.end_row();
+                for (i, attempt) in attempts.iter().enumerate() {
+                    ui.label((i + 1).to_string());
+                    ui.label(format!("{:.2}s", attempt.duration));
+                    ui.label(attempt.actions.len().to_string());
+                    if ui
+                        .selectable_label(self.selected_attempt == Some(i), "Render")
+                        .on_hover_text("Only render this attempt")
+                        .clicked()
+                    {
+                        self.selected_attempt = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn show_replay_statistics(&mut self, dialog: &Modal, ui: &mut egui::Ui) {
+        /// FPS variance above which a warning is shown.
+        const FPS_VARIANCE_THRESHOLD: f64 = 10.0;
+        /// Jitter above which a replay is considered not frame-aligned.
+        const MAX_JITTER_FRAMES: f64 = 0.5;
+        /// Timing entropy (in bits) below which a replay looks bot-made.
+        const BOT_LIKE_ENTROPY: f64 = 3.0;
+        /// Timing entropy (in bits) above which a replay looks human-made.
+        const HUMAN_LIKE_ENTROPY: f64 = 5.0;
+        /// Reaction time standard deviation (in seconds) below which a replay looks bot-made.
+        const BOT_LIKE_REACTION_STDDEV: f64 = 0.002;
+        /// Width of the reaction time histogram buckets, in seconds.
+        const REACTION_BUCKET: f64 = 0.01;
+
+        let (min_fps, max_fps) = self.replay_stats().fps_range;
+        ui.horizontal(|ui| {
+            ui.label(format!("FPS range: {min_fps:.0}–{max_fps:.0} Hz"));
+            if ui
+                .button("Copy to clipboard")
+                .on_hover_text("Copy the actions as a table (up to 1000 actions)")
+                .clicked()
+            {
+                ui.ctx().copy_text(self.replay.to_human_readable_string());
+            }
+        });
+        let stats = self.replay_stats();
+        ui.label(format!(
+            "Action rate: {:.0} BPM average, {:.0} BPM median",
+            stats.average_bpm, stats.median_bpm
+        ))
+        .on_hover_text("240 BPM ≈ 4 clicks per second");
+        if !self.replay.extended.is_empty() {
+            let x_range = self.replay_stats().x_range.clone();
+            ui.label(format!(
+                "X range: [{:.1}, {:.1}]",
+                x_range.start(),
+                x_range.end()
+            ))
+            .on_hover_text(
+                "The horizontal distance covered by the players, i.e. the spatial extent of \
+                the level. Some replay formats don't store positions, in which case this is 0",
+            );
+            ui.label(format!(
+                "{} restarts detected",
+                self.replay_stats().num_restarts
+            ))
+            .on_hover_text(format!(
+                "Frames where the X position drops by more than {} units",
+                Replay::RESTART_X_THRESHOLD
+            ));
+            ui.collapsing("Show attempts", |ui| self.show_attempts(ui));
+        }
+        let num_simultaneous = self.replay_stats().num_simultaneous;
+        ui.label(format!("{num_simultaneous} simultaneous click events"))
+            .on_hover_text(format!(
+                "Clicks within {SIMULTANEOUS_TOLERANCE_FRAMES} frame of each other"
+            ));
+        egui::Grid::new("replay_input_stats_grid")
+            .num_columns(7)
+            .spacing([20.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                for header in [
+                    "", "Presses", "Releases", "Hard", "Regular", "Soft", "Micro",
+                ] {
+                    ui.label(header);
+                }
+                ui.end_row();
+                for (name, stats) in ["Player 1", "Player 2"]
+                    .into_iter()
+                    .zip(self.replay.input_stats_by_player())
+                {
+                    ui.label(name);
+                    for count in [
+                        stats.presses,
+                        stats.releases,
+                        stats.hardclicks,
+                        stats.clicks,
+                        stats.softclicks,
+                        stats.microclicks,
+                    ] {
+                        ui.label(count.to_string());
+                    }
+                    ui.end_row();
+                }
+            });
+        let entropy = self.replay_stats().entropy;
+        ui.horizontal(|ui| {
+            ui.label(format!("Timing entropy: {entropy:.2} bits"))
+                .on_hover_text(
+                    "Unpredictability of the times between actions (1 ms buckets). \
+                    Bots repeat the same intervals and have low entropy",
+                );
+            if entropy < BOT_LIKE_ENTROPY {
+                ui.label(RichText::new("Very bot-like").color(Color32::RED));
+            } else if entropy > HUMAN_LIKE_ENTROPY {
+                ui.label(RichText::new("Human-like").color(Color32::GREEN));
+            }
+        });
+        let reaction_times = &self.replay_stats().reaction_times;
+        if !reaction_times.is_empty() {
+            let count = reaction_times.len() as f64;
+            let mean = reaction_times.iter().sum::<f64>() / count;
+            let stddev = (reaction_times
+                .iter()
+                .map(|t| (t - mean).powi(2))
+                .sum::<f64>()
+                / count)
+                .sqrt();
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Reaction time: {:.0} ms mean, {:.1} ms deviation",
+                    mean * 1000.0,
+                    stddev * 1000.0
+                ))
+                .on_hover_text(
+                    "Delay between the apex of a jump and the next click. Humans react with \
+                    a wide spread, bots always react after the same delay",
+                );
+                if reaction_times.len() > 1 && stddev < BOT_LIKE_REACTION_STDDEV {
+                    ui.label(RichText::new("Very bot-like").color(Color32::RED));
+                }
+            });
+            ui.collapsing("Reaction time histogram", |ui| {
+                use egui_plot::{Bar, BarChart, Plot};
+                let mut buckets: Vec<u32> = vec![];
+                for t in reaction_times {
+                    let bucket = (t / REACTION_BUCKET) as usize;
+                    if bucket >= buckets.len() {
+                        buckets.resize(bucket + 1, 0);
+                    }
+                    buckets[bucket] += 1;
+                }
+                let bars = buckets
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &n)| {
+                        Bar::new((i as f64 + 0.5) * REACTION_BUCKET * 1000.0, n as f64)
+                            .width(REACTION_BUCKET * 1000.0)
+                    })
+                    .collect();
+                Plot::new("reaction_time_histogram")
+                    .height(120.0)
+                    .allow_zoom(false)
+                    .allow_drag(false)
+                    .show(ui, |plot_ui| {
+                        plot_ui.bar_chart(BarChart::new(bars).name("Clicks per 10 ms"));
+                    });
+            });
+        }
+        let jitter = &self.replay_stats().jitter;
+        ui.label(format!(
+            "Timing jitter: {:.3} frames max, {:.3} frames mean",
+            jitter.max_jitter_frames, jitter.mean_jitter_frames
+        ))
+        .on_hover_text(format!(
+            "Distance between the action times and their frames.\nActions per 0.1 frame: {:?}",
+            jitter.jitter_histogram
+        ));
+        if jitter.max_jitter_frames > MAX_JITTER_FRAMES {
+            ui.label(
+                RichText::new(
+                    "This replay is not properly frame-aligned, some actions are more than \
+                    half a frame away from their frame.",
+                )
+                .color(Color32::YELLOW),
+            );
+        }
+        if self.replay_stats().fps_variance > FPS_VARIANCE_THRESHOLD {
+            ui.label(
+                RichText::new(
+                    "This replay changes FPS a lot, which may cause timing artefacts. \
+                    Consider enabling \"Sort actions\" and check for FPS-dependent audio offsets.",
+                )
+                .color(Color32::YELLOW),
+            );
+        }
+
+        ui.checkbox(&mut self.mirror_x, "Mirror X").on_hover_text(
+            "Flip all X positions around the middle of the level in the physics exports below",
+        );
+        if ui
+            .button("Export physics to Blender CSV")
+            .on_hover_text(
+                "Export the position and rotation of each player as keyframes. \
+                Two files are written, one for each player (_p1.csv and _p2.csv)",
+            )
+            .clicked()
+        {
+            if let Some(path) = FileDialog::new().add_filter("CSV", &["csv"]).save_file() {
+                if let Err(e) = self.export_blender_csv(&path) {
+                    log::error!("failed to export physics: {e}");
+                    dialog
+                        .dialog()
+                        .with_title("Failed to export physics")
+                        .with_body(capitalize_first_letter(&e.to_string()))
+                        .with_icon(Icon::Error)
+                        .open();
+                }
+            }
+        }
+
+        if ui
+            .button("Generate heatmap")
+            .on_hover_text(
+                "Save a PNG of where clicks happen over time. \
+                X is time, Y is the player's X position",
+            )
+            .clicked()
+        {
+            if let Some(path) = FileDialog::new().add_filter("PNG", &["png"]).save_file() {
+                if let Err(e) = self.save_heatmap(&path) {
+                    log::error!("failed to save heatmap: {e}");
+                    dialog
+                        .dialog()
+                        .with_title("Failed to save heatmap")
+                        .with_body(capitalize_first_letter(&e.to_string()))
+                        .with_icon(Icon::Error)
+                        .open();
+                }
+            }
+        }
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("Load game events")
+                .on_hover_text(
+                    "Load a JSON array of {\"frame\", \"event_type\"} objects recorded \
+                    by a GD event recorder (e.g. ORB_ACTIVATED, SPEED_PORTAL, CHECKPOINT). \
+                    They are shown as colored markers on the heatmap",
+                )
+                .clicked()
+            {
+                if let Some(path) = FileDialog::new().add_filter("JSON", &["json"]).pick_file() {
+                    if let Err(e) = self.load_game_events(&path) {
+                        log::error!("failed to load game events: {e}");
+                        dialog
+                            .dialog()
+                            .with_title("Failed to load game events")
+                            .with_body(capitalize_first_letter(&e.to_string()))
+                            .with_icon(Icon::Error)
+                            .open();
+                    }
+                }
+            }
+            ui.label(format!("{} game events", self.replay.events.len()));
+        });
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("Diff replays")
+                .on_hover_text("Select another replay and only keep the actions that are not in it")
+                .clicked()
+            {
+                if let Some(file) = FileDialog::new()
+                    .add_filter("Replay file", Replay::SUPPORTED_EXTENSIONS)
+                    .pick_file()
+                {
+                    self.diff_replay(dialog, &file);
+                }
+            }
+            if let Some(diff) = &self.replay_diff {
+                ui.label(format!(
+                    "{} of {} actions are not in the other replay",
+                    diff.actions.len(),
+                    self.replay.actions.len()
+                ));
+            }
+        });
+        if self.replay_diff.is_some()
+            && ui
+                .button("Use diff")
+                .on_hover_text("Replace the current replay with the diff")
+                .clicked()
+        {
+            self.replay = self.replay_diff.take().unwrap();
+            self.replay_modified = true;
+            self.selected_attempt = None;
+            self.clear_replay_caches();
+            self.update_expr = true;
+        }
+    }
+
+    fn show_replay_stage(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.heading("Select replay file");
+
+        let mut dialog = Modal::new(ctx, "replay_stage_dialog");
+
+        ui.collapsing("Timings", |ui| {
+            ui.label("Click type timings. The number is the delay between actions (in seconds). \
+                    If the delay between the current and previous action is bigger than the specified \
+                    timing, the corresponding click type is used.");
+            let t = &mut self.conf.timings;
+
+            drag_value(ui, &mut t.hard, "Hard timing",
+            t.regular..=f64::INFINITY,
+            "Hardclick/hardrelease timing");
+            drag_value(ui, &mut t.regular, "Regular timing", t.soft..=t.hard,
+            "Click/release timing");
+            drag_value(ui, &mut t.soft, "Soft timing", 0.0..=t.regular,
+            "Softclick/softrelease timing");
+            ui.label(format!("Everything below {}s are microclicks/microreleases.", t.soft));
+        });
+
+        ui.collapsing("Volume settings", |ui| {
+            ui.label(
+                "General volume settings. The volume variation variable \
+                defines the range of the random volume offset.",
+            );
+
+            let vol = &mut self.conf.vol_settings;
+            drag_value(
+                ui,
+                &mut vol.volume_var,
+                "Volume variation",
+                0.0..=f32::INFINITY,
+                "Maximum volume variation for each action (+/-)",
+            );
+            drag_value(
+                ui,
+                &mut vol.global_volume,
+                "Global volume",
+                0.0..=f32::INFINITY,
+                "Constant volume multiplier for all actions",
+            );
+            drag_value(
+                ui,
+                &mut vol.simultaneous_click_boost,
+                "Simultaneous click boost",
+                0.0..=f32::INFINITY,
+                "Volume added to clicks that happen together with another click \
+                (for example when both players click at once)",
+            );
+
+            help_text(
+                ui,
+                "How click types are chosen. When classifying by Y velocity, the timings \
+                are used as velocity thresholds (higher velocity = harder click)",
+                |ui| {
+                    egui::ComboBox::from_label("Classify clicks by")
+                        .selected_text(self.conf.classification_mode.to_string())
+                        .show_ui(ui, |ui| {
+                            use ClassificationMode::*;
+                            for mode in [ByTime, ByVelocity] {
+                                ui.selectable_value(
+                                    &mut self.conf.classification_mode,
+                                    mode,
+                                    mode.to_string(),
+                                );
+                            }
+                        });
+                },
+            );
+        });
+
+        ui.collapsing("Spam volume changes", |ui| {
+            ui.label(
+                "Adjusts the volume of 'spam clicks', which are defined as actions within \
+                a maximum time limit, known as the 'spam time'. The volume offset \
+                is based on the delta time between actions, multiplied by the spam \
+                volume offset factor, and clamped \
+                by the maximum spam volume offset. \
+                In short, this can be used to lower the volume of clicks in spams.",
+            );
+
+            let vol = &mut self.conf.vol_settings;
+            ui.checkbox(&mut vol.enabled, "Enable spam volume changes");
+
+            ui.add_enabled_ui(vol.enabled, |ui| {
+                help_text(ui, "Apply spam volume changes to releases", |ui| {
                     ui.checkbox(&mut vol.change_releases_volume, "Change releases volume");
                 });
                 drag_value(
@@ -1167,6 +2366,38 @@ impl App {
         help_text(ui, "Sort actions by time", |ui| {
             ui.checkbox(&mut self.conf.sort_actions, "Sort actions");
         });
+        ui.checkbox(&mut self.conf.invert_inputs, "Invert inputs")
+            .on_hover_text(
+                RichText::new(
+                    "This swaps all button presses with releases—the audio will sound inverted.",
+                )
+                .color(Color32::LIGHT_RED),
+            );
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.conf.remove_releases, "Remove releases")
+                .on_hover_text("Only render click sounds");
+            ui.checkbox(&mut self.conf.remove_clicks, "Remove clicks")
+                .on_hover_text("Only render release sounds");
+        });
+        ui.checkbox(
+            &mut self.conf.remove_redundant_holds,
+            "Remove redundant holds",
+        )
+        .on_hover_text(
+            "Remove presses that follow another press (and releases that follow another \
+                release) of the same player and button",
+        );
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.subsample_preview, "Subsample for preview")
+                .on_hover_text(
+                    "Only keep every N-th action in the render preview.\n\
+                    The final render always uses all actions",
+                );
+            if self.subsample_preview {
+                ui.label("N:");
+                ui.add(DragValue::new(&mut self.subsample_every).range(2..=1000));
+            }
+        });
         ui.separator();
 
         ui.horizontal(|ui| {
@@ -1232,8 +2463,67 @@ impl App {
                 ));
             }
         });
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.base64_replay)
+                    .hint_text("Paste a base64 replay"),
+            );
+            if ui
+                .add_enabled(
+                    !self.base64_replay.trim().is_empty(),
+                    egui::Button::new("Load from base64 string"),
+                )
+                .clicked()
+            {
+                self.load_replay_from_base64(&dialog);
+            }
+        });
+        if num_actions > 0
+            && ui
+                .button("Copy as base64")
+                .on_hover_text(
+                    "Copy the replay as compressed GDR text, \
+                    for sharing it where files can't be uploaded",
+                )
+                .clicked()
+        {
+            match self.replay.to_base64_string(bot::gdr::GdrFormat::MsgPack) {
+                Ok(encoded) => ui.ctx().copy_text(encoded),
+                Err(e) => log::error!("failed to encode replay: {e}"),
+            }
+        }
         if num_actions > 0 {
             ui.label(format!("Replay FPS: {:.2}", self.replay.fps));
+            if self.replay_modified {
+                ui.label(
+                    RichText::new("The replay was modified, reload it to undo the changes")
+                        .color(Color32::YELLOW),
+                );
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Remap button");
+                ui.add(DragValue::new(&mut self.remap_buttons.0).range(1..=3));
+                ui.label("to");
+                ui.add(DragValue::new(&mut self.remap_buttons.1).range(1..=3));
+                if ui
+                    .button("Apply")
+                    .on_hover_text(
+                        "Replace all actions using the first button with the second one \
+                        (1 = click, 2 = left, 3 = right)",
+                    )
+                    .clicked()
+                {
+                    let (from, to) = self.remap_buttons;
+                    let count = self.replay.swap_buttons(from, to);
+                    self.replay_modified |= count > 0;
+                    log::info!("remapped {count} actions from button {from} to {to}");
+                }
+            });
+
+            ui.collapsing("Statistics", |ui| {
+                self.show_replay_statistics(&dialog, ui);
+            });
         }
 
         ui.collapsing("Supported file formats", |ui| {
@@ -1286,6 +2576,65 @@ impl App {
                 .with_icon(Icon::Error)
                 .open();
         }
+        self.dry_run_stats.take();
+    }
+
+    /// Loads replay files and selects clickpack folders that were dropped onto the window,
+    /// and shows a hint while files are dragged over it.
+    fn handle_dropped_files(&mut self, ctx: &egui::Context, dialog: &Modal) {
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("drop_overlay"),
+            ));
+            let screen_rect = ctx.screen_rect();
+            painter.rect_filled(screen_rect, 0.0, Color32::from_black_alpha(192));
+            painter.text(
+                screen_rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop replay or clickpack folder here",
+                egui::TextStyle::Heading.resolve(&ctx.style()),
+                Color32::WHITE,
+            );
+        }
+
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        // the bot belongs to the render thread until it's done
+        if self.render_task.is_some() {
+            return;
+        }
+        for path in dropped.into_iter().filter_map(|f| f.path) {
+            let filename = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default()
+                .to_lowercase();
+            if path.is_dir() {
+                self.select_clickpack(&path);
+                self.stage = if self.replay.has_actions() {
+                    Stage::Render
+                } else {
+                    Stage::SelectReplay
+                };
+            } else if Replay::SUPPORTED_EXTENSIONS
+                .iter()
+                .any(|ext| filename.ends_with(&format!(".{ext}")))
+            {
+                self.replay_path = Some(path.clone());
+                if self.load_replay(dialog, &path).is_ok() {
+                    self.stage = Stage::SelectClickpack;
+                }
+            } else {
+                dialog
+                    .dialog()
+                    .with_title("Unsupported file")
+                    .with_body(format!(
+                        "'{filename}' is not a replay file or a clickpack folder"
+                    ))
+                    .with_icon(Icon::Error)
+                    .open();
+            }
+        }
     }
 
     fn select_clickpack(&mut self, path: &Path) {
@@ -1293,6 +2642,10 @@ impl App {
         self.clickpack_has_noise = bot::dir_has_noise(path);
         self.clickpack_path = Some(path.to_path_buf());
         self.bot = RefCell::new(Bot::new(self.conf.sample_rate));
+        self.num_clipping_files = 0;
+        self.clipping_filenames.clear();
+        self.clickpack_reload_time = None;
+        self.dry_run_stats.take();
     }
 
     fn show_select_clickpack_stage(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
@@ -1365,12 +2718,60 @@ impl App {
                     });
                 }
 
-                help_text(ui, "Reverse all audio files", |ui| {
-                    ui.checkbox(&mut conv_settings.reverse, "Reverse audio")
-                });
-
-                help_text(ui, "Rename all audio files to 1.wav, 2.wav, etc.", |ui| {
-                    ui.checkbox(&mut conv_settings.rename_files, "Rename files")
+                help_text(
+                    ui,
+                    "Resample all audio files to change their pitch. \
+                    Useful for creating detuned variants of a clickpack for layering",
+                    |ui| {
+                        ui.horizontal(|ui| {
+                            let mut enabled = conv_settings.pitch_shift.is_some();
+                            ui.checkbox(&mut enabled, "Pitch shift (semitones)");
+                            if enabled {
+                                let mut semitones =
+                                    conv_settings.pitch_shift.map_or(0.0, |r| 12.0 * r.log2());
+                                ui.add(
+                                    DragValue::new(&mut semitones)
+                                        .range(-24.0..=24.0)
+                                        .speed(0.05),
+                                );
+                                conv_settings.pitch_shift = Some(2.0f32.powf(semitones / 12.0));
+                            } else {
+                                conv_settings.pitch_shift = None;
+                            }
+                        });
+                    },
+                );
+
+                help_text(
+                    ui,
+                    "Remove low-frequency rumble (e.g. from cheap microphones) \
+                    below the cutoff frequency",
+                    |ui| {
+                        ui.horizontal(|ui| {
+                            let mut enabled = conv_settings.high_pass_cutoff_hz.is_some();
+                            ui.checkbox(&mut enabled, "High-pass filter (Hz)");
+                            if enabled {
+                                let cutoff = conv_settings.high_pass_cutoff_hz.get_or_insert(100.0);
+                                ui.add(DragValue::new(cutoff).range(1.0..=20000.0).speed(1.0));
+                            } else {
+                                conv_settings.high_pass_cutoff_hz = None;
+                            }
+                        });
+                    },
+                );
+
+                help_text(ui, "Reverse all audio files", |ui| {
+                    ui.checkbox(&mut conv_settings.reverse, "Reverse audio")
+                });
+
+                help_text(
+                    ui,
+                    "Write all audio files as mono. Otherwise stereo files stay stereo",
+                    |ui| ui.checkbox(&mut conv_settings.force_mono, "Force mono"),
+                );
+
+                help_text(ui, "Rename all audio files to 1.wav, 2.wav, etc.", |ui| {
+                    ui.checkbox(&mut conv_settings.rename_files, "Rename files")
                 });
 
                 help_text(
@@ -1414,6 +2815,22 @@ impl App {
                         },
                     );
                 }
+
+                drag_value(
+                    ui,
+                    &mut conv_settings.fade_in_ms,
+                    "Fade in (ms)",
+                    0..=10000,
+                    "Fade in the start of every audio file, after removing silence",
+                );
+                drag_value(
+                    ui,
+                    &mut conv_settings.fade_out_ms,
+                    "Fade out (ms)",
+                    0..=10000,
+                    "Fade out the end of every audio file, after removing silence",
+                );
+
                 ui.horizontal(|ui| {
                     if ui
                         .button("Convert")
@@ -1464,6 +2881,7 @@ impl App {
 
                             // finished, unload clickpack
                             *self.bot.borrow_mut() = Bot::new(self.conf.sample_rate);
+                            self.dry_run_stats.take();
                         } else {
                             dialog
                                 .dialog()
@@ -1513,13 +2931,46 @@ impl App {
 
             // clickpack_num_sounds only gets set after rendering where the
             // clickpack gets loaded
-            if let Some(num_sounds) = self.clickpack_num_sounds {
-                ui.label(format!(
-                    "Selected clickpack: {filename:?}, {num_sounds} sounds"
-                ));
-            } else {
-                ui.label(format!("Selected clickpack: {filename:?}"));
-            }
+            ui.horizontal(|ui| {
+                if let Some(num_sounds) = self.clickpack_num_sounds {
+                    ui.label(format!(
+                        "Selected clickpack: {filename:?}, {num_sounds} sounds"
+                    ));
+                } else {
+                    ui.label(format!("Selected clickpack: {filename:?}"));
+                }
+
+                let mut button = ui.button("🔄 Reload clickpack").on_hover_text(
+                    "Load the clickpack files again, use this if you edited them on disk",
+                );
+                if let Some(elapsed) = self.clickpack_reload_time {
+                    button = button
+                        .on_hover_text(format!("Last reload took {} ms", elapsed.as_millis()));
+                }
+                if button.clicked() {
+                    let start = Instant::now();
+                    let pitch = if self.conf.pitch_enabled {
+                        self.conf.pitch
+                    } else {
+                        Pitch::NO_PITCH
+                    };
+                    let mut bot = self.bot.borrow_mut();
+                    // drop the old noise file too, so it gets reloaded
+                    bot.noise = None;
+                    if let Err(e) = bot.load_clickpack(clickpack_path, pitch) {
+                        dialog
+                            .dialog()
+                            .with_title("Failed to reload clickpack")
+                            .with_body(e)
+                            .with_icon(Icon::Error)
+                            .open();
+                    }
+                    self.clickpack_num_sounds = Some(bot.clickpack.num_sounds());
+                    self.clickpack_reload_time = Some(start.elapsed());
+                    self.dry_run_stats.take();
+                    log::info!("reloaded clickpack in {:?}", start.elapsed());
+                }
+            });
         }
 
         if let Some(clickpack_path) = &self.clickpack_path {
@@ -1552,6 +3003,34 @@ impl App {
                                     ui.end_row();
                                 }
                             });
+                        ui.collapsing("Preview", |ui| {
+                            ui.label("Click a button to play a random sound of that type");
+                            for (clicks, name) in [
+                                (&bot.clickpack.player1, "player1"),
+                                (&bot.clickpack.player2, "player2"),
+                                (&bot.clickpack.left1, "left1"),
+                                (&bot.clickpack.right1, "right1"),
+                                (&bot.clickpack.left2, "left2"),
+                                (&bot.clickpack.right2, "right2"),
+                            ] {
+                                if !clicks.has_clicks() {
+                                    continue;
+                                }
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.label(format!("{name}:"));
+                                    for (i, typ) in CLICK_TYPE_DIRNAMES.iter().enumerate() {
+                                        let files = &clicks[i];
+                                        if files.is_empty() {
+                                            continue;
+                                        }
+                                        if ui.button(format!("▶ {typ}")).clicked() {
+                                            let file = &files[fastrand::usize(..files.len())];
+                                            bot::play_once(&file.segment, file.segment.sample_rate);
+                                        }
+                                    }
+                                });
+                            }
+                        });
                     } else {
                         ui.label("Structure cannot be displayed since the clickpack is not loaded");
                         ui.horizontal(|ui| {
@@ -1587,9 +3066,20 @@ impl App {
         dialog.show_dialog();
     }
 
-    fn render_replay(&mut self, dialog: &Modal) {
+    /// Collects the click files that are close to clipping in the loaded clickpack.
+    fn update_clipping_files(&mut self) {
+        const CLIPPING_THRESHOLD: f32 = 0.9;
+        let bot = self.bot.borrow();
+        let files = bot.clickpack.clipping_files(CLIPPING_THRESHOLD);
+        self.num_clipping_files = files.len();
+        self.clipping_filenames = files.iter().take(3).map(|f| f.filename.clone()).collect();
+    }
+
+    /// Loads the clickpack with the current pitch settings. Shows an error and returns
+    /// `false` if it failed.
+    fn load_clickpack_for_render(&mut self, dialog: &Modal) -> bool {
         let Some(clickpack_path) = &self.clickpack_path else {
-            return;
+            return false;
         };
 
         // load clickpack
@@ -1607,57 +3097,242 @@ impl App {
                 .with_body(e)
                 .with_icon(Icon::Error)
                 .open();
-            return;
+            return false;
+        }
+
+        if self.seed_enabled {
+            self.bot.borrow_mut().with_seed(self.seed);
         }
 
         self.clickpack_num_sounds = Some(self.bot.borrow().clickpack.num_sounds());
+        self.dry_run_stats.take();
+        self.update_clipping_files();
+        true
+    }
 
-        let start = Instant::now();
-        let segment = self.bot.borrow_mut().render_replay(
-            &self.replay,
-            self.conf.noise,
-            self.conf.noise_volume,
-            self.conf.normalize,
-            if !self.conf.expr_text.is_empty() && self.expr_error.is_empty() {
-                self.conf.expr_variable
-            } else {
-                ExprVariable::None
-            },
-            self.conf.pitch_enabled,
-            self.conf.cut_sounds,
-        );
-        let end = start.elapsed();
-        log::info!("rendered in {end:?}");
+    /// The expression variable to render with, [`ExprVariable::None`] if the expression is
+    /// empty or invalid.
+    fn render_expr_variable(&self) -> ExprVariable {
+        if !self.conf.expr_text.is_empty() && self.expr_error.is_empty() {
+            self.conf.expr_variable
+        } else {
+            ExprVariable::None
+        }
+    }
 
-        let output = self
-            .output
+    /// The selected output file.
+    fn output_path(&self) -> PathBuf {
+        self.output
             .clone()
-            .unwrap_or(PathBuf::from("you_shouldnt_see_this.wav"));
-        let f = std::fs::File::create(output.clone());
+            .unwrap_or(PathBuf::from("you_shouldnt_see_this.wav"))
+    }
 
-        if let Ok(f) = f {
-            if let Err(e) = segment.export_wav(f) {
-                dialog
-                    .dialog()
-                    .with_title("Failed to write output file!")
-                    .with_body(format!(
-                        "{e}. Try running the program as administrator \
-                        or selecting a different directory."
-                    ))
-                    .with_icon(Icon::Error)
-                    .open();
-            }
-        } else if let Err(e) = f {
+    /// Writes `segment` to `output` in the selected output format. Shows an error and
+    /// returns `false` if it failed.
+    fn write_output(&self, dialog: &Modal, output: &Path, segment: &AudioSegment) -> bool {
+        if let Err(e) = write_segment(&self.conf, output, segment) {
             dialog
                 .dialog()
-                .with_title("Failed to open output file!")
+                .with_title("Failed to write output file!")
                 .with_body(format!(
-                    "{e}. Try running the program as administrator \
-                    or selecting a different directory."
+                    "{}. Try running the program as administrator \
+                    or selecting a different directory.",
+                    capitalize_first_letter(&format!("{e:#}"))
                 ))
                 .with_icon(Icon::Error)
                 .open();
+            return false;
+        }
+        true
+    }
+
+    /// The replay to render: the selected attempt or the whole replay, humanised if
+    /// "Humanise" is enabled.
+    fn render_source(&self) -> Cow<'_, Replay> {
+        let replay = match self
+            .selected_attempt
+            .and_then(|i| self.replay_stats().attempts.get(i))
+        {
+            Some(attempt) => Cow::Borrowed(attempt),
+            None => Cow::Borrowed(&self.replay),
+        };
+        if self.conf.humanise_strength > 0.0 {
+            Cow::Owned(
+                self.bot
+                    .borrow_mut()
+                    .apply_humanisation_pass(&replay, self.conf.humanise_strength),
+            )
+        } else {
+            replay
+        }
+    }
+
+    /// Starts rendering the loaded replay on a background thread. The bot is moved to the
+    /// thread until the render is done, see [`App::poll_render_task`].
+    fn render_replay(&mut self, dialog: &Modal) {
+        if !self.load_clickpack_for_render(dialog) {
+            return;
+        }
+
+        let replay = self.render_source().into_owned();
+        let output = self.output_path();
+
+        // Lua states can't be sent to another thread, so scripts are rendered right away
+        #[cfg(feature = "lua")]
+        if !self.script.trim().is_empty() {
+            let start = Instant::now();
+            let result = self
+                .bot
+                .borrow_mut()
+                .run_script(
+                    &replay,
+                    &self.script,
+                    self.conf.noise,
+                    self.conf.noise_volume,
+                    self.conf.normalize,
+                )
+                .and_then(|segment| write_segment(&self.conf, &output, &segment))
+                .map(|_| output);
+            self.show_render_result(dialog, result, start.elapsed());
+            return;
+        }
+
+        self.spawn_render(replay, Some(output));
+    }
+
+    /// Renders `replay` on a background thread and writes it to `output`, or plays it if
+    /// `output` is `None`. The bot is moved to the thread until the render is done, see
+    /// [`App::poll_render_task`].
+    fn spawn_render(&mut self, replay: Replay, output: Option<PathBuf>) {
+        let progress = Arc::new(Mutex::new(RenderProgress {
+            completed: 0,
+            total: replay.actions.len(),
+            result: None,
+        }));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let conf = self.conf.clone();
+        let expr_var = self.render_expr_variable();
+        let mut bot = std::mem::take(&mut *self.bot.borrow_mut());
+
+        let handle = {
+            let progress = progress.clone();
+            let cancel = cancel.clone();
+            let output = output.clone();
+            std::thread::spawn(move || {
+                let result =
+                    render_segment(&mut bot, &replay, &conf, expr_var, &cancel, |completed| {
+                        progress.lock().unwrap().completed = completed;
+                    })
+                    .and_then(|segment| match &output {
+                        Some(output) => write_segment(&conf, output, &segment),
+                        None => {
+                            bot::play_once(&segment, segment.sample_rate);
+                            Ok(())
+                        }
+                    });
+                progress.lock().unwrap().result = Some(result);
+                bot
+            })
+        };
+        self.render_task = Some(RenderTask {
+            progress,
+            cancel,
+            handle,
+            start: Instant::now(),
+            output,
+        });
+    }
+
+    /// Checks whether the background render is done. If it is, takes the bot back from the
+    /// render thread and shows the result.
+    fn poll_render_task(&mut self, ctx: &egui::Context, dialog: &Modal) {
+        let Some(task) = &self.render_task else {
+            return;
+        };
+        if !task.handle.is_finished() {
+            ctx.request_repaint(); // keep the progress bar moving
+            return;
+        }
+        let task = self.render_task.take().unwrap();
+        let result = task
+            .progress
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .result
+            .take();
+        match task.handle.join() {
+            Ok(bot) => *self.bot.borrow_mut() = bot,
+            Err(_) => {
+                log::error!("render thread panicked");
+                // the bot was lost with the thread, the clickpack is reloaded on the
+                // next render
+                *self.bot.borrow_mut() = Bot::new(self.conf.sample_rate);
+                self.dry_run_stats.take();
+            }
+        }
+        // the expression may have been compiled into the placeholder bot meanwhile
+        self.update_expr = true;
+        if task.cancel.load(Ordering::Relaxed) {
+            log::info!("render cancelled");
+            return;
+        }
+        let result = result.unwrap_or_else(|| Err(anyhow::anyhow!("the render thread panicked")));
+        match task.output {
+            Some(output) => {
+                self.show_render_result(dialog, result.map(|_| output), task.start.elapsed())
+            }
+            None => {
+                if let Err(e) = result {
+                    dialog
+                        .dialog()
+                        .with_title("Failed to render preview")
+                        .with_body(capitalize_first_letter(&format!("{e:#}")))
+                        .with_icon(Icon::Error)
+                        .open();
+                }
+            }
         }
+    }
+
+    /// Shows the progress of the background render with a button to cancel it.
+    fn show_render_progress(&self, ui: &mut egui::Ui) {
+        let Some(task) = &self.render_task else {
+            return;
+        };
+        let (completed, total) = {
+            let progress = task.progress.lock().unwrap_or_else(|e| e.into_inner());
+            (progress.completed, progress.total)
+        };
+        ui.horizontal(|ui| {
+            if ui
+                .button("Cancel")
+                .on_hover_text("Stop rendering, the output file is not written")
+                .clicked()
+            {
+                task.cancel.store(true, Ordering::Relaxed);
+            }
+            ui.add(
+                egui::ProgressBar::new(completed as f32 / total.max(1) as f32)
+                    .show_percentage()
+                    .text(format!("Rendering… {completed}/{total} actions")),
+            );
+        });
+    }
+
+    fn show_render_result(&self, dialog: &Modal, result: Result<PathBuf>, end: Duration) {
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                dialog
+                    .dialog()
+                    .with_title("Failed to render")
+                    .with_body(capitalize_first_letter(&format!("{e:#}")))
+                    .with_icon(Icon::Error)
+                    .open();
+                return;
+            }
+        };
+        log::info!("rendered in {end:?}");
 
         let num_actions = self.replay.actions.len();
         let filename = output.file_name().unwrap().to_str().unwrap();
@@ -1673,6 +3348,159 @@ impl App {
             .open();
     }
 
+    /// Renders one WAV file per click type into a folder picked by the user.
+    fn render_stems(&mut self, dialog: &Modal) {
+        let Some(output_dir) = FileDialog::new().pick_folder() else {
+            return;
+        };
+        if !self.load_clickpack_for_render(dialog) {
+            return;
+        }
+
+        let start = Instant::now();
+        let result = self.bot.borrow_mut().render_stems(
+            &self.render_source(),
+            &output_dir,
+            self.render_expr_variable(),
+            self.conf.pitch_enabled,
+            self.conf.cut_sounds,
+        );
+        match result {
+            Ok(_) => {
+                dialog
+                    .dialog()
+                    .with_title("Done!")
+                    .with_body(format!(
+                        "Successfully exported stems to '{}' in {:?}",
+                        output_dir.display(),
+                        start.elapsed()
+                    ))
+                    .with_icon(Icon::Success)
+                    .open();
+            }
+            Err(e) => {
+                log::error!("failed to render stems: {e}");
+                dialog
+                    .dialog()
+                    .with_title("Failed to render stems")
+                    .with_body(capitalize_first_letter(&e.to_string()))
+                    .with_icon(Icon::Error)
+                    .open();
+            }
+        }
+    }
+
+    /// Renders the loaded replay into the composite at the selected time offset.
+    fn add_to_composite(&mut self, dialog: &Modal) {
+        if !self.load_clickpack_for_render(dialog) {
+            return;
+        }
+
+        let mut composite = self.composite.take().unwrap_or_default();
+        // noise and normalization are applied to the whole composite instead
+        let result = self.bot.borrow_mut().render_replay_into(
+            &self.render_source(),
+            &mut composite,
+            self.composite_offset,
+            false,
+            self.conf.noise_volume,
+            false,
+            self.render_expr_variable(),
+            self.conf.pitch_enabled,
+            self.conf.cut_sounds,
+        );
+        self.composite = Some(composite);
+        if let Err(e) = result {
+            dialog
+                .dialog()
+                .with_title("Failed to add replay")
+                .with_body(format!(
+                    "{}. Clear the composite or change the sample rate back.",
+                    capitalize_first_letter(&e.to_string())
+                ))
+                .with_icon(Icon::Error)
+                .open();
+            return;
+        }
+        self.composite_num_replays += 1;
+    }
+
+    fn export_composite(&mut self, dialog: &Modal) {
+        let Some(composite) = &self.composite else {
+            return;
+        };
+        let mut segment = composite.clone();
+        if self.conf.normalize {
+            segment.normalize();
+        }
+
+        let output = self.output_path();
+        if self.write_output(dialog, &output, &segment) {
+            dialog
+                .dialog()
+                .with_title("Done!")
+                .with_body(format!(
+                    "Successfully exported {} replays to '{}'",
+                    self.composite_num_replays,
+                    output.display()
+                ))
+                .with_icon(Icon::Success)
+                .open();
+        }
+    }
+
+    fn show_composite_options(&mut self, dialog: &Modal, ui: &mut egui::Ui) {
+        ui.label(
+            "Render multiple replays into one file. Add the current replay, then go back, \
+            select another replay and add it too. Noise is not added to composites.",
+        );
+        drag_value(
+            ui,
+            &mut self.composite_offset,
+            "Time offset (s)",
+            0.0..=f64::INFINITY,
+            "Where the next replay starts in the composite",
+        );
+        ui.horizontal(|ui| {
+            let can_add = self.clickpack_path.is_some() && self.replay.has_actions();
+            if ui
+                .add_enabled(can_add, egui::Button::new("Add replay"))
+                .on_disabled_hover_text("Please select a clickpack and load a replay")
+                .on_hover_text("Render the current replay into the composite")
+                .clicked()
+            {
+                self.add_to_composite(dialog);
+            }
+            if let Some(composite) = &self.composite {
+                ui.label(format!(
+                    "{} replays, {:.2?}",
+                    self.composite_num_replays,
+                    composite.duration()
+                ));
+            }
+        });
+        ui.horizontal(|ui| {
+            let has_composite = self.composite.is_some();
+            if ui
+                .add_enabled(
+                    has_composite && self.output.is_some(),
+                    egui::Button::new("Export composite"),
+                )
+                .on_disabled_hover_text("Please add a replay and select an output file")
+                .clicked()
+            {
+                self.export_composite(dialog);
+            }
+            if ui
+                .add_enabled(has_composite, egui::Button::new("Clear"))
+                .clicked()
+            {
+                self.composite = None;
+                self.composite_num_replays = 0;
+            }
+        });
+    }
+
     fn show_plot(&mut self, ui: &mut egui::Ui) {
         ui.label(
             "Input a mathematical expression to change the volume multiplier \
@@ -1726,12 +3554,7 @@ impl App {
                     self.expr_error.clear(); // clear errors
 
                     // update namespace so we can check for undefined variables
-                    bot.update_namespace(
-                        &ExtendedAction::default(),
-                        0,
-                        self.replay.last_frame(),
-                        self.replay.fps as _,
-                    );
+                    bot.update_namespace(&ExtendedAction::default(), 0, &self.replay);
 
                     if let Err(e) = bot.eval_expr() {
                         self.expr_error = e.to_string();
@@ -1811,6 +3634,35 @@ impl App {
             }
         });
 
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Group by")
+                .selected_text(self.conf.plot_grouping.to_string())
+                .show_ui(ui, |ui| {
+                    use PlotGrouping::*;
+                    for grouping in [All, ByPlayer, ByClickType, ByTimeBucket(10.0)] {
+                        // keep the bucket size when reselecting the same grouping
+                        let selected = std::mem::discriminant(&self.conf.plot_grouping)
+                            == std::mem::discriminant(&grouping);
+                        if ui
+                            .selectable_label(selected, grouping.to_string())
+                            .clicked()
+                            && !selected
+                        {
+                            self.conf.plot_grouping = grouping;
+                        }
+                    }
+                });
+            if let PlotGrouping::ByTimeBucket(secs) = &mut self.conf.plot_grouping {
+                drag_value(
+                    ui,
+                    secs,
+                    "Bucket size (s)",
+                    0.1..=f64::INFINITY,
+                    "Length of each time bucket, in seconds",
+                );
+            }
+        });
+
         let plot_points = if expr_changed {
             let prev_frame = RefCell::new(0);
 
@@ -1829,8 +3681,7 @@ impl App {
                     self.bot.borrow_mut().update_namespace(
                         &action,
                         *prev_frame.borrow(),
-                        self.replay.last_frame(),
-                        self.replay.fps as _,
+                        &self.replay,
                     );
                     *prev_frame.borrow_mut() = action.frame;
 
@@ -1859,7 +3710,11 @@ impl App {
             )
         };
 
-        let line = Line::new(plot_points).name(self.conf.expr_variable.to_string());
+        let lines = if self.conf.plot_grouping == PlotGrouping::All {
+            vec![Line::new(plot_points).name(self.conf.expr_variable.to_string())]
+        } else {
+            self.grouped_plot_lines()
+        };
         ui.add_space(4.0);
 
         ui.add_enabled_ui(self.expr_error.is_empty() && num_actions > 0, |ui| {
@@ -1868,7 +3723,9 @@ impl App {
                 .data_aspect(self.conf.plot_data_aspect)
                 .y_axis_min_width(4.0);
             plot.show(ui, |plot_ui| {
-                plot_ui.line(line);
+                for line in lines {
+                    plot_ui.line(line);
+                }
             })
             .response
             .on_disabled_hover_text(if num_actions == 0 {
@@ -1877,24 +3734,419 @@ impl App {
                 "The expression is invalid"
             });
         });
-    }
-
-    fn show_render_stage(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
-        ui.heading("Render");
-
-        let mut dialog = Modal::new(ctx, "render_stage_dialog");
 
-        ui.horizontal(|ui| {
-            help_text(
-                ui,
-                "Select the output .wav file.\nYou have to click 'Render' to render the output",
-                |ui| {
+        ui.add_enabled_ui(
+            self.expr_error.is_empty()
+                && !self.conf.expr_text.is_empty()
+                && self.replay.has_actions(),
+            |ui| {
+                if ui
+                    .button("Bake expression")
+                    .on_hover_text(
+                        "Permanently offset the time of every action in the replay by the \
+                        expression. The expression variable is reset to None afterwards, \
+                        so the offset isn't applied twice",
+                    )
+                    .clicked()
+                {
+                    let mut bot = self.bot.borrow_mut();
+                    match self
+                        .replay
+                        .apply_expression_as_time_offset(&self.conf.expr_text, &mut bot)
+                    {
+                        Ok(()) => {
+                            log::info!(
+                                "baked expression into {} actions",
+                                self.replay.actions.len()
+                            );
+                            self.conf.expr_variable = ExprVariable::None;
+                            self.replay_modified = true;
+                        }
+                        Err(e) => self.expr_error = e.to_string(),
+                    }
+                }
+            },
+        );
+    }
+
+    /// Splits the cached plot points into one line per group of the selected
+    /// [`PlotGrouping`].
+    fn grouped_plot_lines(&self) -> Vec<egui_plot::Line> {
+        use egui_plot::{Line, PlotPoints};
+        const COLORS: [Color32; 6] = [
+            Color32::LIGHT_BLUE,
+            Color32::LIGHT_RED,
+            Color32::LIGHT_GREEN,
+            Color32::YELLOW,
+            Color32::GOLD,
+            Color32::KHAKI,
+        ];
+
+        let extended = &self.replay.extended;
+        let mut groups: BTreeMap<i64, Vec<[f64; 2]>> = BTreeMap::new();
+        for point in self.plot_points.iter() {
+            let Some(e) = extended.get((point.x as usize).min(extended.len().saturating_sub(1)))
+            else {
+                continue;
+            };
+            let key = match self.conf.plot_grouping {
+                PlotGrouping::All => 0,
+                PlotGrouping::ByPlayer => e.player2 as i64,
+                PlotGrouping::ByClickType => {
+                    let player = if e.player2 { Player::Two } else { Player::One };
+                    self.replay
+                        .actions_within_frame_range_inclusive(e.frame, e.frame)
+                        .iter()
+                        .find(|a| a.player == player)
+                        .map_or(ClickType::COUNT, |a| a.click.click_type().to_index())
+                        as i64
+                }
+                PlotGrouping::ByTimeBucket(secs) => {
+                    (e.frame as f64 / self.replay.fps / secs).floor() as i64
+                }
+            };
+            groups.entry(key).or_default().push([point.x, point.y]);
+        }
+
+        groups
+            .into_iter()
+            .enumerate()
+            .map(|(i, (key, points))| {
+                let name = match self.conf.plot_grouping {
+                    PlotGrouping::All => self.conf.expr_variable.to_string(),
+                    PlotGrouping::ByPlayer => format!("Player {}", key + 1),
+                    PlotGrouping::ByClickType => {
+                        format!("{:?}", ClickType::from_index(key as usize))
+                    }
+                    PlotGrouping::ByTimeBucket(secs) => {
+                        format!("{:.1}s-{:.1}s", key as f64 * secs, (key + 1) as f64 * secs)
+                    }
+                };
+                Line::new(PlotPoints::new(points))
+                    .name(name)
+                    .color(COLORS[i % COLORS.len()])
+            })
+            .collect()
+    }
+
+    /// Asks for an output file and writes the replay to it with `export`.
+    fn export_replay(
+        &self,
+        dialog: &Modal,
+        filter_name: &str,
+        extension: &str,
+        export: impl FnOnce(&Replay, &mut BufWriter<File>) -> Result<()>,
+    ) {
+        let Some(path) = FileDialog::new()
+            .add_filter(filter_name, &[extension])
+            .save_file()
+        else {
+            return;
+        };
+        log::info!("exporting replay to {path:?}");
+        let result = File::create(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|f| {
+                let mut writer = BufWriter::new(f);
+                export(&self.replay, &mut writer)?;
+                writer.flush()?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            log::error!("failed to export replay: {e}");
+            dialog
+                .dialog()
+                .with_title("Failed to export replay")
+                .with_body(capitalize_first_letter(&e.to_string()))
+                .with_icon(Icon::Error)
+                .open();
+        }
+    }
+
+    fn show_export_options(&mut self, dialog: &Modal, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(self.replay.has_actions(), |ui| {
+            if ui
+                .button("Export HTML visualisation (.html)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "A single-file report with a timeline of the actions, \
+                    statistics and a click density chart",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "HTML", "html", |replay, w| {
+                    replay.export_as_html_visualisation(w)
+                });
+            }
+            if ui
+                .button("Export zBot frame replay (.zbf)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text("zBot replay. Platformer left/right inputs are not supported")
+                .clicked()
+            {
+                self.export_replay(dialog, "zBot frame replay", "zbf", |replay, w| {
+                    w.write_all(&replay.export_zbf()?)?;
+                    Ok(())
+                });
+            }
+            if ui
+                .button("Export FCEUX movie (.fm2)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text("Clicks are mapped to the A button of each player's controller")
+                .clicked()
+            {
+                self.export_replay(dialog, "FCEUX movie", "fm2", |replay, w| {
+                    replay.export_as_fceux_fm2(w)
+                });
+            }
+            if ui
+                .button("Export XML (.xml)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text("Generic XML format for tools that can't import other formats")
+                .clicked()
+            {
+                self.export_replay(dialog, "XML", "xml", |replay, w| replay.export_xml(w));
+            }
+            if ui
+                .button("Export Final Cut Pro markers (.fcpxml)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text("One timeline marker per action, titled by the click type")
+                .clicked()
+            {
+                self.export_replay(dialog, "Final Cut Pro XML", "fcpxml", |replay, w| {
+                    replay.export_as_fcpxml(w)
+                });
+            }
+            if ui
+                .button("Export subtitles (.ass)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "One caption per action, colored by player and positioned \
+                    by the player's X position",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "ASS subtitles", "ass", |replay, w| {
+                    replay.export_as_subtitle_ass(w)
+                });
+            }
+            if ui
+                .button("Export OBS chapter markers (.txt)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text("One chapter per death and spam section, for chaptering stream VODs")
+                .clicked()
+            {
+                self.export_replay(dialog, "Chapter markers", "txt", |replay, w| {
+                    replay.export_as_obs_chapter_markers(w)
+                });
+            }
+            if ui
+                .button("Export OGG chapters (.txt)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "Vorbis comment CHAPTER tags for deaths, FPS changes and spam sections. \
+                    Add them to a rendered .ogg file with `vorbiscomment -a -c`",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "OGG chapters", "txt", |replay, w| {
+                    replay.export_as_ogg_chapters(w)
+                });
+            }
+            if ui
+                .button("Export REAPER regions (.csv)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "One region per click, colored by click type. Import it in the \
+                    REAPER region/marker manager",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "REAPER regions", "csv", |replay, w| {
+                    replay.export_as_reaper_region_file(w)
+                });
+            }
+            if ui
+                .button("Export Audacity labels (.txt)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "Import the labels in Audacity (File → Import → Labels…) \
+                    to inspect the click timings alongside the rendered audio",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "Audacity labels", "txt", |replay, w| {
+                    replay.export_as_audacity_labels(w)
+                });
+            }
+            if ui
+                .button("Export Clickteam Fusion events (.csv)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text("One time_ms,down,player line per action")
+                .clicked()
+            {
+                self.export_replay(dialog, "Clickteam events", "csv", |replay, w| {
+                    replay.export_as_clickteam_event(w)
+                });
+            }
+            if ui
+                .button("Export SMIL (.smil)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "SMIL presentation with one clip of the rendered clicks.wav per action, \
+                    for SMIL-capable players like Totem. Render the replay as clicks.wav \
+                    next to it",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "SMIL", "smil", |replay, w| replay.export_as_smil(w));
+            }
+            if ui
+                .button("Export NSF (.nsf)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "NES Sound Format chiptune that plays a noise channel click on every action",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "NES Sound Format", "nsf", |replay, w| {
+                    replay.export_as_nes_nsf(w)
+                });
+            }
+            if ui
+                .button("Export piano roll (.svg)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text("1600x400 piano roll with one row per click type")
+                .clicked()
+            {
+                self.export_replay(dialog, "Piano roll", "svg", |replay, w| {
+                    replay.export_as_piano_roll_svg(w, 1600, 400)
+                });
+            }
+            if ui
+                .button("Export actions to CSV (.csv)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "One row per action with its time, player, click type and volume offset, \
+                    plus the player position if the replay has it",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "Actions", "csv", |replay, w| replay.export_csv(w));
+            }
+            if ui
+                .button("Export VCV Rack sequence (.csv)")
+                .on_disabled_hover_text("You have to load a replay first")
+                .on_hover_text(
+                    "One sequencer step per action, with the voltage set by the click type",
+                )
+                .clicked()
+            {
+                self.export_replay(dialog, "VCV Rack sequence", "csv", |replay, w| {
+                    replay.export_as_vcv_rack_sequence(w)
+                });
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Export osu! beatmap (.osu)")
+                    .on_disabled_hover_text("You have to load a replay first")
+                    .on_hover_text(
+                        "Every click becomes a hit circle, placed horizontally by the player position",
+                    )
+                    .clicked()
+                {
+                    let circle_size = self.osu_circle_size;
+                    self.export_replay(dialog, "osu! beatmap", "osu", |replay, w| {
+                        replay.export_hitobjects_osu(w, circle_size)
+                    });
+                }
+                drag_value(
+                    ui,
+                    &mut self.osu_circle_size,
+                    "Circle size",
+                    0.0..=10.0,
+                    "Circle size (CS) of the exported beatmap",
+                );
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Export StepMania chart (.sm)")
+                    .on_disabled_hover_text("You have to load a replay first")
+                    .on_hover_text(
+                        "Every action becomes a tap note. Player 1 is on the left two \
+                        columns, player 2 on the right two",
+                    )
+                    .clicked()
+                {
+                    let bpm = self.stepmania_bpm;
+                    self.export_replay(dialog, "StepMania chart", "sm", |replay, w| {
+                        replay.export_as_stepmania_sm(w, bpm)
+                    });
+                }
+                drag_value(
+                    ui,
+                    &mut self.stepmania_bpm,
+                    "BPM",
+                    1.0..=1000.0,
+                    "Tempo of the exported chart, used to place the notes on beats",
+                );
+            });
+            ui.collapsing("Advanced", |ui| {
+                #[cfg(feature = "json-schema")]
+                if ui
+                    .button("Export GDR JSON with schema (.json)")
+                    .on_disabled_hover_text("You have to load a replay first")
+                    .on_hover_text(
+                        "GDR JSON with a $schema field, validated against \
+                        ZCB's GDR JSON schema before writing",
+                    )
+                    .clicked()
+                {
+                    self.export_replay(dialog, "GDR JSON", "json", |replay, w| {
+                        replay.export_as_json_schema_validated(w)
+                    });
+                }
+                if ui
+                    .button("Export TouchOSC layout (index.xml)")
+                    .on_disabled_hover_text("You have to load a replay first")
+                    .on_hover_text(
+                        "Every click becomes a push button, placed by time. \
+                        Zip the file to use it as a .touchosc layout",
+                    )
+                    .clicked()
+                {
+                    self.export_replay(dialog, "TouchOSC layout", "xml", |replay, w| {
+                        replay.export_as_touchosc(w)
+                    });
+                }
+            });
+        });
+    }
+
+    fn show_render_stage(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.heading("Render");
+
+        let mut dialog = Modal::new(ctx, "render_stage_dialog");
+
+        // doesn't need a reparse, see `Config::replay_changed`
+        self.replay.vol_settings.stereo_separation = self.conf.vol_settings.stereo_separation;
+
+        ui.horizontal(|ui| {
+            help_text(
+                ui,
+                "Select the output .wav, .flac or .ogg file.\nYou have to click 'Render' to render the output",
+                |ui| {
                     if ui.button("Select output file").clicked() {
                         if let Some(path) = FileDialog::new()
-                            .add_filter("Supported audio files", &["wav"])
+                            .add_filter("WAV", &["wav"])
+                            .add_filter("FLAC", &["flac"])
+                            .add_filter("OGG", &["ogg"])
                             .save_file()
                         {
                             log::info!("selected output file: {path:?}");
+                            if let Some(format) = AudioFormat::from_path(&path) {
+                                self.conf.output_format = format;
+                            }
                             self.output = Some(path);
                         } else {
                             dialog
@@ -1914,6 +4166,42 @@ impl App {
                 ));
             }
         });
+        ui.horizontal(|ui| {
+            ui.label("Output format:");
+            let mut changed = false;
+            for (format, name) in [
+                (AudioFormat::Wav, "WAV"),
+                (AudioFormat::Flac, "FLAC"),
+                (AudioFormat::Ogg, "OGG"),
+            ] {
+                let enabled = format != AudioFormat::Ogg || cfg!(feature = "ogg");
+                let selected = self.conf.output_format == format;
+                if ui
+                    .add_enabled(enabled, egui::RadioButton::new(selected, name))
+                    .on_disabled_hover_text("ZCB was built without the `ogg` feature")
+                    .clicked()
+                    && !selected
+                {
+                    self.conf.output_format = format;
+                    changed = true;
+                }
+            }
+            if changed {
+                if let Some(output) = &mut self.output {
+                    output.set_extension(self.conf.output_format.extension());
+                }
+            }
+            if self.conf.output_format == AudioFormat::Ogg {
+                drag_value(
+                    ui,
+                    &mut self.conf.ogg_quality,
+                    "Quality",
+                    -0.1..=1.0,
+                    "Vorbis quality, from -0.1 (smallest file) to 1.0 (best quality). \
+                    OGG output requires the `ogg` feature",
+                );
+            }
+        });
 
         ui.separator();
 
@@ -1937,8 +4225,28 @@ impl App {
                         "Noise volume multiplier",
                     );
                 });
+                if self.conf.noise {
+                    drag_value(
+                        ui,
+                        &mut self.conf.sidechain_ratio,
+                        "Sidechain ratio",
+                        0.0..=1.0,
+                        "Duck the noise on every click, like a sidechain compressor. \
+                        The noise drops to this fraction of its volume and recovers over 50 ms. \
+                        1 disables ducking",
+                    );
+                }
             });
 
+            drag_value(
+                ui,
+                &mut self.conf.vol_settings.stereo_separation,
+                "Stereo separation",
+                0.0..=1.0,
+                "Pan player 1 to the left and player 2 to the right in two-player replays. \
+                0 keeps both players centered",
+            );
+
             help_text(
                 ui,
                 "Cut overlapping click sounds, changes the sound significantly in spams",
@@ -1951,6 +4259,18 @@ impl App {
                 "Whether to normalize the output audio\n(make all samples to be in range of 0-1)",
             );
 
+            help_text(
+                ui,
+                "Add human-like variations: action times slowly shift back and forth \
+                and the volume drifts. 0 disables it",
+                |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut self.conf.humanise_strength, 0.0..=1.0)
+                            .text("Humanise"),
+                    );
+                },
+            );
+
             // audio framerate inputfield
             ui.horizontal(|ui| {
                 u32_edit_field_min1(ui, &mut self.conf.sample_rate);
@@ -1968,8 +4288,114 @@ impl App {
             self.show_plot(ui);
         });
 
+        ui.collapsing("Export", |ui| {
+            self.show_export_options(&dialog, ui);
+        });
+
         ui.separator();
 
+        ui.collapsing("Render preview", |ui| {
+            let every = self.subsample_every;
+            let key = self.subsample_preview.then_some(every);
+            let replay = if self.subsample_preview {
+                ui.label(format!("Previewing every {every}-th action"));
+                if self
+                    .subsampled_replay
+                    .as_ref()
+                    .is_none_or(|(n, _)| *n != every)
+                {
+                    self.subsampled_replay = Some((every, self.replay.subsample(every)));
+                }
+                &self.subsampled_replay.as_ref().unwrap().1
+            } else {
+                &self.replay
+            };
+            let bot = self.bot.borrow();
+            let stats = match self.dry_run_stats.get() {
+                Some((k, stats)) if k == key => stats,
+                _ => {
+                    let stats = bot.dry_run(replay);
+                    self.dry_run_stats.set(Some((key, stats)));
+                    stats
+                }
+            };
+            ui.label(format!("Actions: {}", stats.action_count));
+            ui.label(format!("Output duration: {:.2?}", stats.estimated_duration));
+            if bot.has_clicks() {
+                ui.label(format!("Click sounds: {}", stats.total_sounds));
+                ui.label(format!(
+                    "Estimated peak memory usage: {:.1} MB",
+                    stats.estimated_memory_mb
+                ));
+                drop(bot);
+                if ui
+                    .button("▶ Play preview")
+                    .on_hover_text("Render the replay in the background and play it")
+                    .clicked()
+                {
+                    self.spawn_render(replay.clone(), None);
+                }
+            } else {
+                ui.label("The clickpack is loaded when rendering, sound statistics will be shown after that.");
+            }
+        });
+
+        if self.num_clipping_files > 0 {
+            ui.label(
+                RichText::new(format!(
+                    "{} click files are near clipping – consider reducing their volume \
+                    or enabling normalisation. ({})",
+                    self.num_clipping_files,
+                    self.clipping_filenames.join(", ")
+                ))
+                .color(Color32::YELLOW),
+            );
+        }
+
+        #[cfg(feature = "lua")]
+        ui.collapsing("Script", |ui| {
+            ui.label(
+                "Lua script that decides the click type, volume and pitch of every action. \
+                It gets the `actions` array and returns an array of overrides like \
+                { volume = 1.0, pitch = 1.0, click_type = \"HardClick\" }. \
+                Leave empty to render normally.",
+            );
+            if ui.button("Load script").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .add_filter("Lua script", &["lua"])
+                    .pick_file()
+                {
+                    match std::fs::read_to_string(&path) {
+                        Ok(script) => self.script = script,
+                        Err(e) => log::error!("failed to read script {path:?}: {e}"),
+                    }
+                }
+            }
+            ui.add(
+                egui::TextEdit::multiline(&mut self.script)
+                    .code_editor()
+                    .desired_rows(6)
+                    .desired_width(f32::INFINITY),
+            );
+        });
+
+        ui.checkbox(&mut self.composite_enabled, "Composite mode")
+            .on_hover_text("Mix multiple replays into one output file");
+        if self.composite_enabled {
+            self.show_composite_options(&dialog, ui);
+            ui.separator();
+        }
+
+        help_text(
+            ui,
+            "Render with a fixed seed, so the same replay and settings always give \
+            the same output. Reload the replay to also seed the random volume",
+            |ui| {
+                ui.checkbox(&mut self.seed_enabled, "Seed");
+                ui.add_enabled(self.seed_enabled, DragValue::new(&mut self.seed));
+            },
+        );
+
         let has_output = self.output.is_some();
         let has_clicks = self.clickpack_path.is_some();
         let has_actions = self.replay.has_actions();
@@ -1989,9 +4415,20 @@ impl App {
                     .on_hover_text("Start rendering the replay.\nThis might take some time!")
                     .clicked()
                 {
-                    self.render_replay(&dialog); // TODO: run this on a separate thread
+                    self.render_replay(&dialog);
                 }
             });
+            if ui
+                .add_enabled(has_clicks && has_actions, egui::Button::new("Render stems"))
+                .on_disabled_hover_text("Please select a clickpack and load a replay")
+                .on_hover_text(
+                    "Render every click type into its own .wav file in a folder, \
+                    for mixing them separately in a DAW",
+                )
+                .clicked()
+            {
+                self.render_stems(&dialog);
+            }
             if !is_enabled {
                 ui.label(error_text);
             }
@@ -2001,8 +4438,30 @@ impl App {
     }
 
     fn show_clickpack_db(&mut self, _ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let cached_at = CACHED_DB_TIMESTAMP.load(Ordering::Relaxed);
+            if cached_at != 0 {
+                ui.label(
+                    RichText::new(format!(
+                        "Using cached database (last updated {})",
+                        format_time_ago(cached_at)
+                    ))
+                    .color(Color32::YELLOW),
+                );
+            }
+            if ui
+                .button("Clear cache")
+                .on_hover_text("Remove the offline copy of the clickpack database")
+                .clicked()
+            {
+                if let Err(e) = clear_db_cache() {
+                    log::error!("failed to clear clickpack database cache: {e}");
+                }
+            }
+        });
+
         self.clickpack_db
-            .show(ui, &ureq_get, &|| FileDialog::new().pick_folder());
+            .show(ui, &ureq_get_cached, &|| FileDialog::new().pick_folder());
 
         if let Some(select_path) = self.clickpack_db.select_clickpack.take() {
             self.select_clickpack(&select_path);
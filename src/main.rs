@@ -3,7 +3,7 @@ mod gui;
 use bot::*;
 use clap::{Parser, ValueEnum};
 use std::{
-    io::BufReader,
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
 };
 
@@ -26,13 +26,82 @@ impl std::fmt::Display for ArgExprVariable {
     }
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Wav,
+    Flac,
+    /// Requires the `ogg` feature
+    Ogg,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ConvertFormat {
+    /// GDReplayFormat (MessagePack)
+    Gdr,
+    /// GDReplayFormat (JSON)
+    GdrJson,
+    /// FCEUX movie
+    Fm2,
+    /// osu! beatmap
+    Osu,
+    /// yBot 2 macro
+    Ybot2,
+    /// Generic XML
+    Xml,
+    /// REAPER region list (.csv)
+    ReaperRegions,
+    /// Final Cut Pro X markers (.fcpxml)
+    Fcpxml,
+    /// Advanced SubStation Alpha subtitles (.ass)
+    Ass,
+    /// StepMania chart (.sm)
+    Sm,
+    /// VCV Rack sequence (.csv)
+    VcvRack,
+    /// Self-contained HTML report (.html)
+    Html,
+    /// Clickteam Fusion timing events (.csv)
+    Clickteam,
+    /// Piano roll (.svg)
+    PianoRoll,
+    /// Vorbis comment chapter tags (.txt)
+    OggChapters,
+    /// NES Sound Format (.nsf)
+    Nsf,
+    /// SMIL presentation (.smil)
+    Smil,
+    /// zBot frame replay (.zbf)
+    Zbf,
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Run without any arguments to launch GUI.", long_about = None)]
 struct Args {
-    #[arg(long, help = "Path to replay file")]
-    replay: String,
-    #[arg(long, help = "Path to clickpack folder")]
-    clicks: String,
+    #[arg(long, help = "Path to replay file", required_unless_present = "stdin")]
+    replay: Option<String>,
+    #[arg(
+        long,
+        help = "Read the replay from the standard input instead of --replay"
+    )]
+    stdin: bool,
+    #[arg(
+        long,
+        help = "Replay format (file extension, e.g. mhr.json) used for --stdin if it can't be detected"
+    )]
+    stdin_format: Option<String>,
+    #[arg(
+        long,
+        help = "Path to clickpack folder",
+        required_unless_present_any = [
+            "convert",
+            "timestamps",
+            "dump_graph",
+            "chapters",
+            "ml_matrix",
+            "export_csv"
+        ]
+    )]
+    clicks: Option<String>,
     #[arg(
         long,
         help = "Whether to overlay the noise.* file in the clickpack directory",
@@ -41,8 +110,32 @@ struct Args {
     noise: bool,
     #[arg(long, help = "Noise volume multiplier", default_value_t = 1.0)]
     noise_volume: f32,
+    #[arg(
+        long,
+        help = "Duck the noise to this fraction of its volume on every click (1 disables ducking)",
+        default_value_t = 1.0
+    )]
+    sidechain_ratio: f32,
+    #[arg(
+        long,
+        help = "Strength of the timing and volume humanisation, from 0 (off) to 1",
+        default_value_t = 0.0
+    )]
+    humanise_strength: f32,
     #[arg(long, short, help = "Path to output file", default_value_t = String::from("output.wav"))]
     output: String,
+    #[arg(
+        long,
+        value_enum,
+        help = "Audio format of the output file. Defaults to the format of the output extension, or WAV"
+    )]
+    output_format: Option<OutputFormat>,
+    #[arg(
+        long,
+        help = "Vorbis quality of OGG outputs, from -0.1 (smallest) to 1.0 (best)",
+        default_value_t = 0.5
+    )]
+    ogg_quality: f32,
     #[arg(
         long,
         help = "Whether to normalize the output audio (make all samples to be in range of 0-1)",
@@ -110,6 +203,23 @@ struct Args {
         default_value_t = 0.2
     )]
     volume_var: f32,
+    #[arg(
+        long,
+        help = "Volume added to clicks that happen together with another click",
+        default_value_t = 0.0
+    )]
+    simultaneous_click_boost: f32,
+    #[arg(
+        long,
+        help = "Pan player 1 left and player 2 right in two-player replays (0 to 1)",
+        default_value_t = 0.0
+    )]
+    stereo_separation: f32,
+    #[arg(
+        long,
+        help = "Seed for the random click, pitch and volume choices, for reproducible output"
+    )]
+    seed: Option<u64>,
     #[arg(long, help = "Audio framerate", default_value_t = 48000)]
     sample_rate: u32,
     #[arg(long, help = "Sort actions by time / frame", default_value_t = true)]
@@ -130,6 +240,66 @@ struct Args {
         default_value_t = false
     )]
     cut_sounds: bool,
+    #[arg(
+        long,
+        help = "Convert the replay and write it to this path instead of rendering"
+    )]
+    convert: Option<String>,
+    #[arg(
+        long,
+        help = "Write a list of action timestamps (time_secs,click_type,player) to this path instead of rendering"
+    )]
+    timestamps: Option<String>,
+    #[arg(
+        long,
+        help = "Write the action graph in DOT format to this path instead of rendering. \
+                Requires the `graph` feature"
+    )]
+    dump_graph: Option<String>,
+    #[arg(
+        long,
+        help = "Write OBS chapter markers (one per death and spam section) to this path \
+                instead of rendering"
+    )]
+    chapters: Option<String>,
+    #[arg(
+        long,
+        help = "Write a CSV matrix of input events (one row per --ml-bucket-frames frames) \
+                for machine learning to this path instead of rendering"
+    )]
+    ml_matrix: Option<String>,
+    #[arg(long, help = "Frames per row of --ml-matrix", default_value_t = 1)]
+    ml_bucket_frames: u32,
+    #[arg(
+        long,
+        help = "Write the actions as CSV to this path. Still renders if --clicks is set"
+    )]
+    export_csv: Option<String>,
+    #[arg(long, value_enum, help = "Output format of --convert", default_value_t = ConvertFormat::Gdr)]
+    format: ConvertFormat,
+    #[arg(
+        long,
+        help = "Circle size of the beatmap when converting to osu!",
+        default_value_t = 4.0
+    )]
+    osu_circle_size: f32,
+    #[arg(
+        long,
+        help = "BPM of the chart when converting to StepMania",
+        default_value_t = 120.0
+    )]
+    sm_bpm: f64,
+    #[arg(
+        long,
+        help = "Load render settings from this GUI config file. Overrides the settings passed as flags"
+    )]
+    config_base: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Config file layered on top of --config-base (or the flags). Only the settings it \
+                contains are used"
+    )]
+    config_overlay: Option<PathBuf>,
 }
 
 #[cfg(windows)]
@@ -167,14 +337,11 @@ fn main() {
 
 /// Run command line interface
 fn run_cli(mut args: Args) {
-    // open replay
-    let f = std::fs::File::open(args.replay.clone()).expect("failed to open replay file");
-
-    let replay_filename = Path::new(&args.replay)
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap();
+    if args.config_base.is_some() || args.config_overlay.is_some() {
+        let (base, overlay) = (args.config_base.clone(), args.config_overlay.clone());
+        gui::apply_layered_config(&mut args, base.as_ref(), overlay.as_ref())
+            .expect("failed to load config");
+    }
 
     let pitch = if args.pitch_enabled {
         Pitch {
@@ -200,22 +367,73 @@ fn run_cli(mut args: Args) {
         change_releases_volume: args.change_releases_volume,
         global_volume: args.global_volume,
         volume_var: args.volume_var,
+        simultaneous_click_boost: args.simultaneous_click_boost,
+        stereo_separation: args.stereo_separation,
     };
 
-    // create bot and load clickpack
-    let mut bot = Bot::new(args.sample_rate);
-    bot.load_clickpack(&PathBuf::from(args.clicks), pitch)
-        .expect("failed to load clickpack");
-
     // parse replay
-    let format = ReplayType::guess_format(replay_filename).expect("failed to guess format");
-    let replay = Replay::build()
+    let mut builder = Replay::build()
         .with_timings(timings)
         .with_vol_settings(vol_settings)
         .with_extended(true)
-        .with_sort_actions(args.sort_actions)
-        .parse(format, BufReader::new(f))
-        .unwrap();
+        .with_sort_actions(args.sort_actions);
+    if let Some(seed) = args.seed {
+        builder = builder.with_seed(seed);
+    }
+    let replay = if args.stdin {
+        let fallback = args.stdin_format.as_ref().map(|ext| {
+            ReplayType::guess_format(&format!("stdin.{ext}")).expect("unknown --stdin-format")
+        });
+        builder
+            .import_from_stdin(fallback)
+            .expect("failed to read replay from stdin")
+    } else {
+        parse_replay_file(builder, Path::new(args.replay.as_ref().unwrap()))
+    };
+
+    if let Some(output) = &args.convert {
+        convert_replay(&replay, Path::new(output), &args).expect("failed to convert replay");
+        return;
+    }
+    if let Some(output) = &args.dump_graph {
+        dump_graph(&replay, Path::new(output)).expect("failed to dump action graph");
+        return;
+    }
+    if let Some(output) = &args.chapters {
+        let f = std::fs::File::create(output).expect("failed to create chapters file");
+        replay
+            .export_as_obs_chapter_markers(BufWriter::new(f))
+            .expect("failed to write chapters");
+        return;
+    }
+    if let Some(output) = &args.export_csv {
+        let f = std::fs::File::create(output).expect("failed to create CSV file");
+        replay
+            .export_csv(BufWriter::new(f))
+            .expect("failed to write CSV");
+        if args.clicks.is_none() {
+            return;
+        }
+    }
+    if let Some(output) = &args.ml_matrix {
+        write_ml_matrix(&replay, Path::new(output), args.ml_bucket_frames)
+            .expect("failed to write ML matrix");
+        return;
+    }
+    if let Some(output) = &args.timestamps {
+        let f = std::fs::File::create(output).expect("failed to create timestamps file");
+        Bot::export_action_timestamps(&replay, BufWriter::new(f))
+            .expect("failed to write timestamps");
+        return;
+    }
+
+    // create bot and load clickpack
+    let mut bot = Bot::new(args.sample_rate);
+    if let Some(seed) = args.seed {
+        bot.with_seed(seed);
+    }
+    bot.load_clickpack(&PathBuf::from(args.clicks.unwrap()), pitch)
+        .expect("failed to load clickpack");
 
     // try to compile volume expression to check for errors
     if !args.volume_expr.is_empty() {
@@ -223,45 +441,183 @@ fn run_cli(mut args: Args) {
             .expect("failed to compile volume expression");
 
         // check for undefined vars
-        bot.update_namespace(
-            &ExtendedAction::default(),
-            0,
-            replay.last_frame(),
-            replay.fps as _,
-        );
+        bot.update_namespace(&ExtendedAction::default(), 0, &replay);
         bot.eval_expr().expect("failed to evaluate expression");
     }
 
+    let replay = if args.humanise_strength > 0.0 {
+        bot.apply_humanisation_pass(&replay, args.humanise_strength)
+    } else {
+        replay
+    };
+    let expr_var = if !args.volume_expr.is_empty() {
+        match args.expr_variable {
+            ArgExprVariable::None => ExprVariable::None,
+            ArgExprVariable::Value => ExprVariable::Value,
+            ArgExprVariable::TimeOffset => ExprVariable::TimeOffset,
+            ArgExprVariable::Variation => ExprVariable::Variation {
+                negative: args.expr_negative,
+            },
+        }
+    } else {
+        ExprVariable::None
+    };
+
     // render output file
-    let segment = bot.render_replay(
-        &replay,
-        args.noise,
-        args.noise_volume,
-        args.normalize,
-        if !args.volume_expr.is_empty() {
-            match args.expr_variable {
-                ArgExprVariable::None => ExprVariable::None,
-                ArgExprVariable::Value => ExprVariable::Value,
-                ArgExprVariable::TimeOffset => ExprVariable::TimeOffset,
-                ArgExprVariable::Variation => ExprVariable::Variation {
-                    negative: args.expr_negative,
-                },
-            }
-        } else {
-            ExprVariable::None
-        },
-        args.pitch_enabled,
-        args.cut_sounds,
-    );
+    let segment = if args.noise && args.sidechain_ratio < 1.0 {
+        bot.render_with_sidechain_compression(
+            &replay,
+            args.noise_volume,
+            args.sidechain_ratio,
+            args.normalize,
+            expr_var,
+            args.pitch_enabled,
+            args.cut_sounds,
+        )
+        .expect("failed to render")
+    } else {
+        bot.render_replay(
+            &replay,
+            args.noise,
+            args.noise_volume,
+            args.normalize,
+            expr_var,
+            args.pitch_enabled,
+            args.cut_sounds,
+        )
+    };
 
     // save
     if args.output.is_empty() {
         log::warn!("output path is empty, defaulting to 'output.wav'");
         args.output = String::from("output.wav"); // can't save to empty path
-    } else if !args.output.ends_with(".wav") {
-        log::warn!("output path is not a .wav, however the output format is always .wav");
     }
+    let output = Path::new(&args.output);
+    let path_format = AudioFormat::from_path(output);
+    let format = match args.output_format {
+        Some(OutputFormat::Wav) => AudioFormat::Wav,
+        Some(OutputFormat::Flac) => AudioFormat::Flac,
+        Some(OutputFormat::Ogg) => AudioFormat::Ogg,
+        None => path_format.unwrap_or_default(),
+    };
+    if path_format != Some(format) {
+        log::warn!(
+            "output path is not a .{}, however the output format is {format:?}",
+            format.extension()
+        );
+    }
+
+    segment
+        .export_to_path(output, format, args.ogg_quality)
+        .unwrap();
+}
+
+/// Writes the action graph of the replay to `output` in DOT format.
+#[cfg(feature = "graph")]
+fn dump_graph(replay: &Replay, output: &Path) -> anyhow::Result<()> {
+    use bot::petgraph::dot::Dot;
+    let graph = replay.to_action_graph();
+    log::info!(
+        "writing action graph with {} nodes and {} edges",
+        graph.node_count(),
+        graph.edge_count()
+    );
+    std::fs::write(output, format!("{:?}", Dot::new(&graph)))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "graph"))]
+fn dump_graph(_replay: &Replay, _output: &Path) -> anyhow::Result<()> {
+    anyhow::bail!("zcb3 was built without the `graph` feature")
+}
+
+/// Writes [`Replay::to_sparse_matrix`] as CSV with a header row and 0/1 cells.
+fn write_ml_matrix(replay: &Replay, output: &Path, bucket_frames: u32) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(std::fs::File::create(output)?);
+    writeln!(writer, "{}", Replay::SPARSE_MATRIX_COLUMNS.join(","))?;
+    for row in replay.to_sparse_matrix(bucket_frames) {
+        let cells: Vec<&str> = row.iter().map(|&b| if b { "1" } else { "0" }).collect();
+        writeln!(writer, "{}", cells.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
 
-    let f = std::fs::File::create(args.output).unwrap();
-    segment.export_wav(f).unwrap();
+/// Parses the replay at `path`, guessing the format from the file name.
+fn parse_replay_file(builder: Replay, path: &Path) -> Replay {
+    let replay_filename = path.file_name().unwrap().to_str().unwrap();
+    let format = ReplayType::guess_format(replay_filename).expect("failed to guess format");
+    #[cfg(feature = "mmap")]
+    let replay = {
+        let f = std::fs::File::open(path).expect("failed to open replay file");
+        builder.parse_memory_mapped(format, &f).unwrap()
+    };
+    #[cfg(not(feature = "mmap"))]
+    let replay = {
+        let data = std::fs::read(path).expect("failed to read replay file");
+        builder.parse_bytes(format, &data).unwrap()
+    };
+    replay
+}
+
+/// Converts the replay to another format and writes it to `output`.
+fn convert_replay(replay: &Replay, output: &Path, args: &Args) -> anyhow::Result<()> {
+    let format = args.format;
+    log::info!("converting replay to {format:?}");
+    match format {
+        ConvertFormat::Gdr => replay.to_gdr().to_file(output, gdr::GdrFormat::MsgPack)?,
+        ConvertFormat::GdrJson => replay.to_gdr().to_file(output, gdr::GdrFormat::Json)?,
+        ConvertFormat::Fm2 => {
+            replay.export_as_fceux_fm2(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::Osu => replay.export_hitobjects_osu(
+            BufWriter::new(std::fs::File::create(output)?),
+            args.osu_circle_size,
+        )?,
+        ConvertFormat::Xml => replay.export_xml(BufWriter::new(std::fs::File::create(output)?))?,
+        ConvertFormat::ReaperRegions => {
+            replay.export_as_reaper_region_file(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::Fcpxml => {
+            replay.export_as_fcpxml(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::Ass => {
+            replay.export_as_subtitle_ass(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::VcvRack => {
+            replay.export_as_vcv_rack_sequence(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::Zbf => replay.export_zbf_to_file(output)?,
+        ConvertFormat::Smil => {
+            replay.export_as_smil(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::Nsf => {
+            replay.export_as_nes_nsf(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::OggChapters => {
+            replay.export_as_ogg_chapters(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::Clickteam => {
+            replay.export_as_clickteam_event(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::PianoRoll => replay.export_as_piano_roll_svg(
+            BufWriter::new(std::fs::File::create(output)?),
+            1600,
+            400,
+        )?,
+        ConvertFormat::Html => {
+            replay.export_as_html_visualisation(BufWriter::new(std::fs::File::create(output)?))?
+        }
+        ConvertFormat::Sm => replay
+            .export_as_stepmania_sm(BufWriter::new(std::fs::File::create(output)?), args.sm_bpm)?,
+        ConvertFormat::Ybot2 => replay.to_ybot2(
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(output)?,
+        )?,
+    }
+    Ok(())
 }